@@ -118,8 +118,7 @@ fn conv_def(data: &Data, endian: &Ident) -> TokenStream {
                     });
                     quote! {
                         Self {
-                            #(#recurse),*,
-                            ..Default::default()
+                            #(#recurse),*
                         }
                     }
                 },
@@ -152,7 +151,7 @@ fn conv_back_def(data: &Data, endian: &Ident) -> TokenStream {
         Data::Struct(ref data) => {
             match data.fields {
                 Fields::Named(ref fields) => {
-                    let recurse = fields.named.iter().filter_map(|f| {
+                    let recurse: Vec<_> = fields.named.iter().filter_map(|f| {
                         let name = &f.ident;
                         let (_, skip, alt_name) = filter_attrs(&f.attrs, endian, name.clone().unwrap());
                         if !skip {
@@ -162,11 +161,20 @@ fn conv_back_def(data: &Data, endian: &Ident) -> TokenStream {
                         } else {
                             None
                         }
-                    });
-                    quote! {
-                        Self {
-                            #(#recurse),*,
-                            ..Default::default()
+                    }).collect();
+                    // skipped fields are left as their defaults
+                    if recurse.len() < fields.named.len() {
+                        quote! {
+                            Self {
+                                #(#recurse),*,
+                                ..Default::default()
+                            }
+                        }
+                    } else {
+                        quote! {
+                            Self {
+                                #(#recurse),*
+                            }
                         }
                     }
                 },
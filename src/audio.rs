@@ -1,49 +1,98 @@
-use std::fs;
+use std::{collections::{BTreeMap, HashMap}, fs};
 use zerocopy::{ByteOrder, LE, BE};
 use serde::{Serialize, Deserialize};
 use std::path::Path;
-use log::{error, info};
+use log::{error, info, warn};
 
 use lotrc_rs_proc::OrderedData;
-use super::types::{OrderedData, OrderedDataVec, Crc};
+use super::types::{OrderedData, OrderedDataVec, Crc, FORMAT};
+use super::read_write::Format;
 
 #[derive(Debug, Default, Clone, OrderedData, Serialize, Deserialize)]
 pub struct Header {
     pub const0x2: u32,
-    pub n1: u32,
-    pub n2: u32,
-    pub n3: u32,
-    pub n4: u32,
-    pub n5: u32,
-    pub n6: u32,
-    pub n7: u32,
+    pub events: u32,
+    pub state_groups: u32,
+    pub switch_groups: u32,
+    pub game_parameters: u32,
+    pub banks: u32,
+    pub busses: u32,
+    pub aux_busses: u32,
 }
 
+/// A name from the wwise project, key is the game's crc of the name and id is the wwise id of it
 #[derive(Debug, Default, Clone, OrderedData, Serialize, Deserialize)]
-pub struct Obj1 {
+pub struct Id {
     pub key: Crc,
-    pub val: u32,
+    #[serde(alias = "val", default)]
+    pub id: u32,
 }
 
 #[derive(Debug, Default, Clone, OrderedData, Serialize, Deserialize)]
-pub struct Obj2 {
-    pub unk_0: u32,
-    pub unk_1: u32,
+pub struct GroupHeader {
+    pub key: Crc,
+    pub id: u32,
     pub n: u32,
 }
 
+/// A state or switch group and the states / switches in it
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Group {
+    pub key: Crc,
+    #[serde(default)]
+    pub id: u32,
+    pub vals: Vec<Id>,
+}
+
+#[derive(Deserialize)]
+struct OldGroupHeader {
+    unk_0: Crc,
+    unk_1: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum GroupVals {
+    Group(Group),
+    Old((OldGroupHeader, Vec<Id>)),
+}
+
+/// Also accepts the `[{unk_0, unk_1, n}, [..]]` pairs of older dumps
+fn deserialize_groups<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<Group>, D::Error> {
+    Ok(Vec::<GroupVals>::deserialize(deserializer)?.into_iter().map(|x| match x {
+        GroupVals::Group(val) => val,
+        GroupVals::Old((header, vals)) => Group { key: header.unk_0, id: header.unk_1, vals },
+    }).collect())
+}
+
+/// The wwise id of a name, a 32 bit fnv-1 hash of the lowercase name
+pub fn wwise_id(name: &str) -> u32 {
+    name.to_lowercase().bytes().fold(2166136261u32, |h, c| h.wrapping_mul(16777619) ^ c as u32)
+}
+
+/// The contents of WWiseIDTable.bin, laid out like the namespaces of a wwise id header
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct AudioTable {
     #[serde(skip)]
     pub header: Header,
-    pub obj1s: Vec<Obj1>,
-    pub obj2s: Vec<(Obj2, Vec<Obj1>)>,
-    pub obj3s: Vec<(Obj2, Vec<Obj1>)>,
-    pub obj4s: Vec<Obj1>,
-    pub obj5s: Vec<Obj1>,
-    pub obj6s: Vec<Obj1>,
-    pub obj7s: Vec<Obj1>,
+    #[serde(alias = "obj1s")]
+    pub events: Vec<Id>,
+    #[serde(alias = "obj2s", deserialize_with = "deserialize_groups")]
+    pub state_groups: Vec<Group>,
+    #[serde(alias = "obj3s", deserialize_with = "deserialize_groups")]
+    pub switch_groups: Vec<Group>,
+    #[serde(alias = "obj4s")]
+    pub game_parameters: Vec<Id>,
+    #[serde(alias = "obj5s")]
+    pub banks: Vec<Id>,
+    #[serde(alias = "obj6s")]
+    pub busses: Vec<Id>,
+    #[serde(alias = "obj7s")]
+    pub aux_busses: Vec<Id>,
     pub extra: Vec<Crc>,
+    /// The soundbanks each event is in, found when dumping the table next to its banks. It isn't part of the table
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub event_banks: BTreeMap<String, Vec<String>>,
 }
 
 impl AudioTable {
@@ -65,83 +114,142 @@ impl AudioTable {
         fs::write(path, self.to_data::<O>()).unwrap();
     }
 
+    fn groups_from_data<O: ByteOrder + 'static>(data: &[u8], offset: &mut usize, n: u32) -> Vec<Group> {
+        let mut groups = Vec::with_capacity(n as usize);
+        for _ in 0..n {
+            let header: GroupHeader = OrderedData::from_bytes::<O>(&data[*offset..]);
+            *offset += GroupHeader::size::<O>();
+            let vals: Vec<Id> = OrderedDataVec::from_bytes::<O>(&data[*offset..], header.n as usize);
+            *offset += vals.size::<O>();
+            groups.push(Group { key: header.key, id: header.id, vals });
+        }
+        groups
+    }
+
+    fn groups_to_data<O: ByteOrder + 'static>(groups: &[Group]) -> impl Iterator<Item = u8> + '_ {
+        groups.iter().flat_map(|group| GroupHeader {
+            key: group.key.clone(),
+            id: group.id,
+            n: group.vals.len() as u32,
+        }.dump_bytes::<O>().into_iter().chain(group.vals.dump_bytes::<O>()))
+    }
+
     pub fn from_data<O: ByteOrder + 'static>(data: &[u8]) -> Self {
-        let header: Header = OrderedData::from_bytes::<O>(&data);
+        let header: Header = OrderedData::from_bytes::<O>(data);
         let mut offset = Header::size::<O>();
-        let obj1s: Vec<Obj1> = OrderedDataVec::from_bytes::<O>(&data[offset..], header.n1 as usize);
-        offset += obj1s.size::<O>();
-        let mut obj2s = Vec::with_capacity(header.n2 as usize);
-        for _ in 0..header.n2 {
-            let obj: Obj2 = OrderedData::from_bytes::<O>(&data[offset..]);
-            offset += Obj2::size::<O>();
-            let objs: Vec<Obj1> = OrderedDataVec::from_bytes::<O>(&data[offset..], obj.n as usize);
-            offset += objs.size::<O>();
-            obj2s.push((obj, objs));
-        }
-        let mut obj3s = Vec::with_capacity(header.n3 as usize);
-        for _ in 0..header.n3 {
-            let obj: Obj2 = OrderedData::from_bytes::<O>(&data[offset..]);
-            offset += Obj2::size::<O>();
-            let objs: Vec<Obj1> = OrderedDataVec::from_bytes::<O>(&data[offset..], obj.n as usize);
-            offset += objs.size::<O>();
-            obj3s.push((obj, objs));
-        }
-        let obj4s: Vec<Obj1> = OrderedDataVec::from_bytes::<O>(&data[offset..], header.n4 as usize);
-        offset += obj4s.size::<O>();
-        let obj5s: Vec<Obj1> = OrderedDataVec::from_bytes::<O>(&data[offset..], header.n5 as usize);
-        offset += obj5s.size::<O>();
-        let obj6s: Vec<Obj1> = OrderedDataVec::from_bytes::<O>(&data[offset..], header.n6 as usize);
-        offset += obj6s.size::<O>();
-        let obj7s: Vec<Obj1> = OrderedDataVec::from_bytes::<O>(&data[offset..], header.n7 as usize);
-        offset += obj7s.size::<O>();
+        let events: Vec<Id> = OrderedDataVec::from_bytes::<O>(&data[offset..], header.events as usize);
+        offset += events.size::<O>();
+        let state_groups = Self::groups_from_data::<O>(data, &mut offset, header.state_groups);
+        let switch_groups = Self::groups_from_data::<O>(data, &mut offset, header.switch_groups);
+        let game_parameters: Vec<Id> = OrderedDataVec::from_bytes::<O>(&data[offset..], header.game_parameters as usize);
+        offset += game_parameters.size::<O>();
+        let banks: Vec<Id> = OrderedDataVec::from_bytes::<O>(&data[offset..], header.banks as usize);
+        offset += banks.size::<O>();
+        let busses: Vec<Id> = OrderedDataVec::from_bytes::<O>(&data[offset..], header.busses as usize);
+        offset += busses.size::<O>();
+        let aux_busses: Vec<Id> = OrderedDataVec::from_bytes::<O>(&data[offset..], header.aux_busses as usize);
+        offset += aux_busses.size::<O>();
         let n = (data.len() - offset) / 4;
         let extra: Vec<Crc> = OrderedDataVec::from_bytes::<O>(&data[offset..], n);
 
-        Self {
+        let val = Self {
             header,
-            obj1s,
-            obj2s,
-            obj3s,
-            obj4s,
-            obj5s,
-            obj6s,
-            obj7s,
-            extra
-        }
+            events,
+            state_groups,
+            switch_groups,
+            game_parameters,
+            banks,
+            busses,
+            aux_busses,
+            extra,
+            event_banks: BTreeMap::new(),
+        };
+        val.check();
+        val
     }
 
     pub fn to_data<O: ByteOrder + 'static>(&self) -> Vec<u8> {
         self.header.dump_bytes::<O>().into_iter()
-        .chain(self.obj1s.dump_bytes::<O>())
-        .chain(self.obj2s.iter().flat_map(|(obj, objs)| obj.dump_bytes::<O>().into_iter().chain(objs.dump_bytes::<O>())))
-        .chain(self.obj3s.iter().flat_map(|(obj, objs)| obj.dump_bytes::<O>().into_iter().chain(objs.dump_bytes::<O>())))
-        .chain(self.obj4s.dump_bytes::<O>())
-        .chain(self.obj5s.dump_bytes::<O>())
-        .chain(self.obj6s.dump_bytes::<O>())
-        .chain(self.obj7s.dump_bytes::<O>())
+        .chain(self.events.dump_bytes::<O>())
+        .chain(Self::groups_to_data::<O>(&self.state_groups))
+        .chain(Self::groups_to_data::<O>(&self.switch_groups))
+        .chain(self.game_parameters.dump_bytes::<O>())
+        .chain(self.banks.dump_bytes::<O>())
+        .chain(self.busses.dump_bytes::<O>())
+        .chain(self.aux_busses.dump_bytes::<O>())
         .chain(self.extra.dump_bytes::<O>())
         .collect()
     }
 
+    /// Every name and wwise id in the table, group names included
+    fn entries(&self) -> impl Iterator<Item = (&Crc, u32)> {
+        self.events.iter().map(|x| (&x.key, x.id))
+        .chain(self.state_groups.iter().chain(&self.switch_groups)
+            .flat_map(|x| std::iter::once((&x.key, x.id)).chain(x.vals.iter().map(|x| (&x.key, x.id)))))
+        .chain(self.game_parameters.iter().chain(&self.banks).chain(&self.busses).chain(&self.aux_busses).map(|x| (&x.key, x.id)))
+    }
+
+    fn entries_mut(&mut self) -> impl Iterator<Item = (&Crc, &mut u32)> {
+        self.events.iter_mut().map(|x| (&x.key, &mut x.id))
+        .chain(self.state_groups.iter_mut().chain(self.switch_groups.iter_mut())
+            .flat_map(|x| std::iter::once((&x.key, &mut x.id)).chain(x.vals.iter_mut().map(|x| (&x.key, &mut x.id)))))
+        .chain(self.game_parameters.iter_mut().chain(self.banks.iter_mut()).chain(self.busses.iter_mut()).chain(self.aux_busses.iter_mut())
+            .map(|x| (&x.key, &mut x.id)))
+    }
+
+    /// Names the events of the banks found by sounds::event_banks
+    pub fn set_event_banks(&mut self, found: &HashMap<u32, Vec<String>>) {
+        self.event_banks = self.events.iter().filter_map(|x| found.get(&x.id).map(|banks| (x.key.to_string(), banks.clone()))).collect();
+    }
+
+    /// The names of the wwise ids in the table
+    pub fn names(&self) -> HashMap<u32, String> {
+        self.entries().map(|(key, id)| (id, key.to_string())).collect()
+    }
+
+    /// Warns if known names have wwise ids that don't match them
+    fn check(&self) {
+        let (known, bad) = self.entries().filter_map(|(key, id)| key.str().map(|name| wwise_id(name) != id))
+            .fold((0, 0), |(known, bad), x| (known + 1, bad + x as usize));
+        if bad != 0 {
+            warn!("{} of {} named audio ids don't match the wwise id of their name", bad, known);
+        }
+    }
+
     pub fn to_file<P: AsRef<Path>>(&self, path: P) {
         if let Some(path) = path.as_ref().parent() {
             fs::create_dir_all(path).ok();
         }
-        fs::write(path.as_ref().with_extension("audio.json"), serde_json::to_string_pretty(&self).unwrap()).unwrap();
+        let format = *FORMAT.lock().unwrap();
+        fs::write(path.as_ref().with_extension(format!("audio.{}", format.extension())), format.to_vec(self)).unwrap();
     }
 
+    /// Entries without an id, e.g. newly added sounds, get the wwise id of their name
     pub fn from_file<P: AsRef<Path>>(path: P) -> Self {
-        let mut val = serde_json::from_slice::<Self>(&fs::read(path.as_ref().with_extension("json")).unwrap()).unwrap();
+        let path = path.as_ref();
+        let format = path.extension().and_then(|x| x.to_str()).and_then(Format::from_extension);
+        let (format, path) = match format {
+            Some(format) => (format, path.to_path_buf()),
+            None => (Format::Json, path.with_extension("json")),
+        };
+        let mut val = format.deserialize::<Self>(&fs::read(&path).unwrap()).unwrap_or_else(|e| panic!("Could not read {:?}: {}", path, e));
+        for (key, id) in val.entries_mut() {
+            if *id == 0 {
+                if let Some(name) = key.str() {
+                    *id = wwise_id(name);
+                }
+            }
+        }
         val.header = Header {
             const0x2: 2,
-            n1: val.obj1s.len() as u32,
-            n2: val.obj2s.len() as u32,
-            n3: val.obj3s.len() as u32,
-            n4: val.obj4s.len() as u32,
-            n5: val.obj5s.len() as u32,
-            n6: val.obj6s.len() as u32,
-            n7: val.obj7s.len() as u32
+            events: val.events.len() as u32,
+            state_groups: val.state_groups.len() as u32,
+            switch_groups: val.switch_groups.len() as u32,
+            game_parameters: val.game_parameters.len() as u32,
+            banks: val.banks.len() as u32,
+            busses: val.busses.len() as u32,
+            aux_busses: val.aux_busses.len() as u32,
         };
         val
     }
-}
\ No newline at end of file
+}
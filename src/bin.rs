@@ -169,6 +169,14 @@ impl Tex {
         }
     }
 
+    pub fn info_mut(&mut self) -> &mut TextureInfo {
+        match self {
+            Self::Texture(val) => &mut val.info,
+            Self::CubeTexture(val) => &mut val.info,
+            Self::Unknown(_, info) => info
+        }
+    }
+
     // pub fn data(&self) -> &Vec<Vec<u8>> {
     //     match self {
     //         Self::Texture(val) => &val.levels,
@@ -177,13 +185,14 @@ impl Tex {
     //     }
     // }
 
-    pub fn to_file(&self, writer: Writer) {
+    /// Writes the texture with its mask named after the level's `gamemodes`
+    pub fn to_file(&self, writer: Writer, gamemodes: &[Crc]) {
         match self {
-            Self::Texture(val) => val.to_file(writer),
-            Self::CubeTexture(val) => val.to_file(writer),
+            Self::Texture(val) => val.to_file(writer, gamemodes),
+            Self::CubeTexture(val) => val.to_file(writer, gamemodes),
             Self::Unknown(vals, info, ) => {
                 let name = writer.name();
-                writer.with_extension("json").write(&serde_json::to_vec_pretty(info).unwrap());
+                writer.write_masked(info, gamemodes);
                 for (i, val) in vals.iter().enumerate() {
                     writer.with_file_name(format!("{}-{}.bin", name, i)).write(val);
                 }
@@ -191,8 +200,8 @@ impl Tex {
         }
     }
 
-    pub fn from_file(reader: Reader) -> Self {
-        let info: TextureInfo = serde_json::from_slice(&&reader.with_extension("json").read()).unwrap();
+    pub fn from_file(reader: Reader, gamemodes: &[Crc]) -> Self {
+        let info: TextureInfo = reader.read_masked(gamemodes);
         match info.kind {
             0 | 7 | 8 => Self::Texture(Texture::from_file(reader, info)),
             1 | 9 => Self::CubeTexture(CubeTexture::from_file(reader, info)),
@@ -431,8 +440,8 @@ impl Texture {
         }
     }
 
-    pub fn to_file(&self, writer: Writer) {
-        writer.with_extension("json").write(&serde_json::to_vec_pretty(&self.info).unwrap());
+    pub fn to_file(&self, writer: Writer, gamemodes: &[Crc]) {
+        writer.write_masked(&self.info, gamemodes);
         let mut dds = ddsfile::Dds::new_d3d(ddsfile::NewD3dParams {
             height: self.info.height as u32,
             width: self.info.width as u32,
@@ -523,8 +532,8 @@ impl CubeTexture {
         }
     }    
     
-    pub fn to_file(&self, writer: Writer) {
-        writer.with_extension("json").write(&serde_json::to_vec_pretty(&self.info).unwrap());
+    pub fn to_file(&self, writer: Writer, gamemodes: &[Crc]) {
+        writer.write_masked(&self.info, gamemodes);
         let mut dds = ddsfile::Dds::new_d3d(ddsfile::NewD3dParams { 
             height: self.info.height as u32,
             width: self.info.width as u32,
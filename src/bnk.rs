@@ -0,0 +1,451 @@
+use std::{collections::HashMap, fs, path::Path};
+use zerocopy::{ByteOrder, LE, BE};
+use serde::Serialize;
+use log::{info, warn};
+
+use lotrc_rs_proc::OrderedData;
+use super::types::{OrderedData, OrderedDataVec};
+
+#[derive(Debug, Default, Clone, OrderedData, Serialize)]
+pub struct BankHeader {
+    pub version: u32,
+    pub id: u32,
+}
+
+/// An entry of DIDX, where a wem is in DATA
+#[derive(Debug, Default, Clone, OrderedData, Serialize)]
+pub struct IndexEntry {
+    pub id: u32,
+    pub offset: u32,
+    pub size: u32,
+}
+
+/// A HIRC object, data is everything after the id
+#[derive(Debug, Default, Clone)]
+pub struct HircObj {
+    pub kind: u8,
+    pub id: u32,
+    pub data: Vec<u8>,
+}
+
+impl HircObj {
+    pub const SOUND: u8 = 2;
+    pub const ACTION: u8 = 3;
+    pub const EVENT: u8 = 4;
+
+    pub fn kind_name(&self) -> &'static str {
+        match self.kind {
+            1 => "State",
+            2 => "Sound",
+            3 => "Action",
+            4 => "Event",
+            5 => "RanSeqContainer",
+            6 => "SwitchContainer",
+            7 => "ActorMixer",
+            8 => "Bus",
+            9 => "LayerContainer",
+            10 => "MusicSegment",
+            11 => "MusicTrack",
+            12 => "MusicSwitch",
+            13 => "MusicRanSeq",
+            14 => "Attenuation",
+            15 => "DialogueEvent",
+            16 => "FeedbackBus",
+            17 => "FeedbackNode",
+            18 => "FxShareSet",
+            19 => "FxCustom",
+            20 => "AuxBus",
+            21 => "LFO",
+            22 => "Envelope",
+            _ => "Unknown",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Section {
+    Header(BankHeader, Vec<u8>),
+    Index(Vec<IndexEntry>),
+    Data(Vec<u8>),
+    Hirc(Vec<HircObj>),
+    Other([u8; 4], Vec<u8>),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ActionInfo {
+    pub id: u32,
+    pub kind: u16,
+    pub target: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EventInfo {
+    pub id: u32,
+    pub name: Option<String>,
+    pub actions: Vec<ActionInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SoundInfo {
+    pub id: u32,
+    pub source: u32,
+    pub embedded: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Listing {
+    pub header: BankHeader,
+    pub name: Option<String>,
+    pub wems: Vec<IndexEntry>,
+    pub objects: HashMap<&'static str, usize>,
+    pub events: Vec<EventInfo>,
+    pub sounds: Vec<SoundInfo>,
+}
+
+/// A wwise soundbank, sections that aren't understood are kept as is
+#[derive(Debug, Default, Clone)]
+pub struct Bank {
+    pub big_endian: bool,
+    pub sections: Vec<Section>,
+}
+
+impl Bank {
+    pub fn parse<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref();
+        info!("Parsing soundbank {}", path.file_stem().unwrap().to_str().unwrap());
+        let data = fs::read(path).unwrap();
+        assert!(data.starts_with(b"BKHD"), "Invalid soundbank data");
+        // the version is small, so read it both ways to tell the platform
+        if u32::from_bytes::<LE>(&data[8..]) < 0x1000 {
+            Self::from_data::<LE>(&data)
+        } else {
+            Self::from_data::<BE>(&data)
+        }
+    }
+
+    pub fn dump<P: AsRef<Path>>(&self, path: P) {
+        fs::write(path, if self.big_endian { self.to_data::<BE>() } else { self.to_data::<LE>() }).unwrap();
+    }
+
+    pub fn version(&self) -> u32 {
+        self.sections.iter().find_map(|x| match x {
+            Section::Header(header, _) => Some(header.version),
+            _ => None,
+        }).unwrap_or_default()
+    }
+
+    fn hirc_from_data<O: ByteOrder + 'static>(data: &[u8]) -> Vec<HircObj> {
+        let n = u32::from_bytes::<O>(data) as usize;
+        let mut offset = 4;
+        let mut objs = Vec::with_capacity(n);
+        for _ in 0..n {
+            let kind = data[offset];
+            let size = u32::from_bytes::<O>(&data[offset + 1..]) as usize;
+            let id = u32::from_bytes::<O>(&data[offset + 5..]);
+            objs.push(HircObj { kind, id, data: data[offset + 9..offset + 5 + size].to_vec() });
+            offset += 5 + size;
+        }
+        objs
+    }
+
+    fn hirc_to_data<O: ByteOrder + 'static>(objs: &[HircObj]) -> Vec<u8> {
+        let mut data = (objs.len() as u32).dump_bytes::<O>();
+        for obj in objs {
+            data.push(obj.kind);
+            data.extend((obj.data.len() as u32 + 4).dump_bytes::<O>());
+            data.extend(obj.id.dump_bytes::<O>());
+            data.extend(&obj.data);
+        }
+        data
+    }
+
+    pub fn from_data<O: ByteOrder + 'static>(data: &[u8]) -> Self {
+        let mut sections = vec![];
+        let mut offset = 0;
+        while offset + 8 <= data.len() {
+            let tag: [u8; 4] = data[offset..offset + 4].try_into().unwrap();
+            let size = u32::from_bytes::<O>(&data[offset + 4..]) as usize;
+            let vals = &data[offset + 8..offset + 8 + size];
+            offset += 8 + size;
+            sections.push(match &tag {
+                b"BKHD" => Section::Header(OrderedData::from_bytes::<O>(vals), vals[BankHeader::size::<O>()..].to_vec()),
+                b"DIDX" => Section::Index(OrderedDataVec::from_bytes::<O>(vals, vals.len() / IndexEntry::size::<O>())),
+                b"DATA" => Section::Data(vals.to_vec()),
+                b"HIRC" => Section::Hirc(Self::hirc_from_data::<O>(vals)),
+                _ => Section::Other(tag, vals.to_vec()),
+            });
+        }
+        Self { big_endian: std::any::TypeId::of::<O>() == std::any::TypeId::of::<BE>(), sections }
+    }
+
+    pub fn to_data<O: ByteOrder + 'static>(&self) -> Vec<u8> {
+        let mut data = vec![];
+        for section in &self.sections {
+            let (tag, vals) = match section {
+                Section::Header(header, extra) => (*b"BKHD", header.dump_bytes::<O>().into_iter().chain(extra.iter().cloned()).collect()),
+                Section::Index(entries) => (*b"DIDX", entries.dump_bytes::<O>()),
+                Section::Data(vals) => (*b"DATA", vals.clone()),
+                Section::Hirc(objs) => (*b"HIRC", Self::hirc_to_data::<O>(objs)),
+                Section::Other(tag, vals) => (*tag, vals.clone()),
+            };
+            data.extend(tag);
+            data.extend((vals.len() as u32).dump_bytes::<O>());
+            data.extend(vals);
+        }
+        data
+    }
+
+    pub fn index(&self) -> &[IndexEntry] {
+        self.sections.iter().find_map(|x| match x {
+            Section::Index(entries) => Some(&entries[..]),
+            _ => None,
+        }).unwrap_or_default()
+    }
+
+    fn data(&self) -> &[u8] {
+        self.sections.iter().find_map(|x| match x {
+            Section::Data(vals) => Some(&vals[..]),
+            _ => None,
+        }).unwrap_or_default()
+    }
+
+    pub fn objects(&self) -> &[HircObj] {
+        self.sections.iter().find_map(|x| match x {
+            Section::Hirc(objs) => Some(&objs[..]),
+            _ => None,
+        }).unwrap_or_default()
+    }
+
+    /// The embedded wems, by id
+    pub fn wems(&self) -> Vec<(u32, &[u8])> {
+        let data = self.data();
+        self.index().iter().map(|x| (x.id, &data[x.offset as usize..(x.offset + x.size) as usize])).collect()
+    }
+
+    fn read_u32(&self, data: &[u8]) -> u32 {
+        if self.big_endian { u32::from_bytes::<BE>(data) } else { u32::from_bytes::<LE>(data) }
+    }
+
+    fn write_u32(&self, val: u32, data: &mut [u8]) {
+        if self.big_endian { val.to_bytes::<BE>(data) } else { val.to_bytes::<LE>(data) }
+    }
+
+    /// The action ids of an event
+    pub fn event_actions(&self, obj: &HircObj) -> Vec<u32> {
+        if self.version() >= 122 {
+            // the count became a variable length int
+            let mut n = 0usize;
+            let mut offset = 0;
+            loop {
+                let c = obj.data[offset];
+                n = (n << 7) | (c & 0x7F) as usize;
+                offset += 1;
+                if c & 0x80 == 0 { break; }
+            }
+            (0..n).map(|i| self.read_u32(&obj.data[offset + i * 4..])).collect()
+        } else {
+            let n = self.read_u32(&obj.data) as usize;
+            (0..n).map(|i| self.read_u32(&obj.data[4 + i * 4..])).collect()
+        }
+    }
+
+    /// The action type and target object of an action
+    pub fn action_target(&self, obj: &HircObj) -> (u16, u32) {
+        let kind = if self.big_endian { u16::from_bytes::<BE>(&obj.data) } else { u16::from_bytes::<LE>(&obj.data) };
+        (kind, self.read_u32(&obj.data[2..]))
+    }
+
+    /// Offset (into the object data) of the source id of a sound and whether the wem is embedded in the bank.
+    /// Only known for the older layout with a 32 bit stream type, which the game uses
+    fn sound_source(&self, obj: &HircObj) -> Option<(usize, bool)> {
+        (self.version() <= 88 && obj.data.len() >= 24).then(|| (8, self.read_u32(&obj.data[4..]) == 0))
+    }
+
+    pub fn listing(&self, names: &HashMap<u32, String>) -> Listing {
+        let objs = self.objects();
+        let lookup = objs.iter().map(|x| (x.id, x)).collect::<HashMap<_, _>>();
+        let mut objects = HashMap::new();
+        for obj in objs {
+            *objects.entry(obj.kind_name()).or_default() += 1;
+        }
+        let header = self.sections.iter().find_map(|x| match x {
+            Section::Header(header, _) => Some(header.clone()),
+            _ => None,
+        }).unwrap_or_default();
+        Listing {
+            name: names.get(&header.id).cloned(),
+            header,
+            wems: self.index().to_vec(),
+            objects,
+            events: objs.iter().filter(|x| x.kind == HircObj::EVENT).map(|x| EventInfo {
+                id: x.id,
+                name: names.get(&x.id).cloned(),
+                actions: self.event_actions(x).into_iter().map(|id| {
+                    let (kind, target) = lookup.get(&id).filter(|x| x.kind == HircObj::ACTION)
+                        .map(|x| self.action_target(x)).unwrap_or_default();
+                    ActionInfo { id, kind, target }
+                }).collect(),
+            }).collect(),
+            sounds: objs.iter().filter(|x| x.kind == HircObj::SOUND).filter_map(|x| self.sound_source(x).map(|(offset, embedded)| SoundInfo {
+                id: x.id,
+                source: self.read_u32(&x.data[offset..]),
+                embedded,
+            })).collect(),
+        }
+    }
+
+    /// Writes every embedded wem as <id>.wem and a bank.json listing
+    pub fn extract<P: AsRef<Path>>(&self, path: P, names: &HashMap<u32, String>) {
+        let path = path.as_ref();
+        fs::create_dir_all(path).unwrap();
+        for (id, data) in self.wems() {
+            fs::write(path.join(format!("{}.wem", id)), data).unwrap();
+        }
+        fs::write(path.join("bank.json"), serde_json::to_string_pretty(&self.listing(names)).unwrap()).unwrap();
+    }
+
+    /// Replaces embedded wems, they must have the same format as the ones they replace.
+    /// DATA is laid out again with the original alignment and the sizes of embedded sounds are updated
+    pub fn replace_wems(&mut self, wems: &HashMap<u32, Vec<u8>>) -> Result<usize, String> {
+        let old = self.wems().into_iter().map(|(id, x)| (id, x.to_vec())).collect::<Vec<_>>();
+        for (id, data) in wems {
+            let (_, current) = old.iter().find(|(x, _)| x == id).ok_or_else(|| format!("Bank has no wem {}", id))?;
+            if wem_format(current) != wem_format(data) {
+                return Err(format!("Wem {} has a different format than the one it replaces", id));
+            }
+        }
+        let index = self.index().to_vec();
+        let align = index.iter().map(|x| x.offset).filter(|x| *x != 0)
+            .map(|x| 1 << x.trailing_zeros().min(11)).min().unwrap_or(16);
+        let mut data = vec![];
+        let mut new_index = Vec::with_capacity(index.len());
+        for (entry, (_, current)) in index.iter().zip(&old) {
+            data.resize(data.len().next_multiple_of(align), 0);
+            let val = wems.get(&entry.id).unwrap_or(current);
+            new_index.push(IndexEntry { id: entry.id, offset: data.len() as u32, size: val.len() as u32 });
+            data.extend(val);
+        }
+
+        let moved = index.iter().zip(&new_index).filter(|(a, b)| a.offset != b.offset || a.size != b.size)
+            .map(|(a, b)| (a.id, (a.clone(), b.clone()))).collect::<HashMap<_, _>>();
+        let mut objs = self.objects().to_vec();
+        for obj in objs.iter_mut().filter(|x| x.kind == HircObj::SOUND) {
+            let Some((offset, true)) = self.sound_source(obj) else { continue };
+            let Some((a, b)) = moved.get(&self.read_u32(&obj.data[offset..])) else { continue };
+            // file id, offset then size follow the source id
+            if self.read_u32(&obj.data[offset + 8..]) == a.offset && self.read_u32(&obj.data[offset + 12..]) == a.size {
+                self.write_u32(b.offset, &mut obj.data[offset + 8..]);
+                self.write_u32(b.size, &mut obj.data[offset + 12..]);
+            } else {
+                warn!("Sound {} doesn't match the index entry of wem {}, left as is", obj.id, a.id);
+            }
+        }
+
+        for section in self.sections.iter_mut() {
+            match section {
+                Section::Index(entries) => *entries = new_index.clone(),
+                Section::Data(vals) => *vals = data.clone(),
+                Section::Hirc(vals) => *vals = objs.clone(),
+                _ => ()
+            }
+        }
+        Ok(wems.len())
+    }
+}
+
+/// The codec, channels and sample rate from the fmt chunk of a riff / rifx wem
+fn wem_format(data: &[u8]) -> Option<&[u8]> {
+    let big_endian = data.starts_with(b"RIFX");
+    let mut offset = 12;
+    while offset + 8 <= data.len() {
+        let size = if big_endian { u32::from_bytes::<BE>(&data[offset + 4..]) } else { u32::from_bytes::<LE>(&data[offset + 4..]) } as usize;
+        if &data[offset..offset + 4] == b"fmt " {
+            return data.get(offset + 8..offset + 16);
+        }
+        offset += 8 + size + (size & 1);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wem(codec: u16, len: usize) -> Vec<u8> {
+        let mut data = b"RIFF".to_vec();
+        data.extend(((len + 28) as u32).to_le_bytes());
+        data.extend(b"WAVEfmt ");
+        data.extend(16u32.to_le_bytes());
+        data.extend(codec.to_le_bytes());
+        data.extend([1, 0, 0x44, 0xAC, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend(b"data");
+        data.extend((len as u32).to_le_bytes());
+        data.extend((0..len).map(|x| x as u8));
+        data
+    }
+
+    /// A bank with two embedded wems, the second is played by a sound
+    fn bank<O: ByteOrder + 'static>() -> Bank {
+        let (a, b) = (wem(2, 10), wem(2, 20));
+        let index = vec![
+            IndexEntry { id: 100, offset: 0, size: a.len() as u32 },
+            IndexEntry { id: 200, offset: a.len().next_multiple_of(16) as u32, size: b.len() as u32 },
+        ];
+        let mut data = a;
+        data.resize(data.len().next_multiple_of(16), 0);
+        data.extend(b);
+        let sound = [0u32, 0, 200, 200, index[1].offset, index[1].size].iter().flat_map(|x| x.dump_bytes::<O>()).collect();
+        Bank {
+            big_endian: std::any::TypeId::of::<O>() == std::any::TypeId::of::<BE>(),
+            sections: vec![
+                Section::Header(BankHeader { version: 72, id: 1 }, vec![0; 8]),
+                Section::Index(index),
+                Section::Data(data),
+                Section::Hirc(vec![HircObj { kind: HircObj::SOUND, id: 5, data: sound }]),
+                Section::Other(*b"STID", vec![1, 2, 3, 4]),
+            ],
+        }
+    }
+
+    fn round_trip<O: ByteOrder + 'static>() {
+        let data = bank::<O>().to_data::<O>();
+        let val = Bank::from_data::<O>(&data);
+        assert_eq!(val.sections.len(), 5);
+        assert_eq!(val.version(), 72);
+        assert_eq!(val.to_data::<O>(), data);
+    }
+
+    #[test]
+    fn round_trip_le() {
+        round_trip::<LE>();
+    }
+
+    #[test]
+    fn round_trip_be() {
+        round_trip::<BE>();
+    }
+
+    #[test]
+    fn replace_wems() {
+        let mut val = bank::<LE>();
+        let new = wem(2, 40);
+        assert_eq!(val.replace_wems(&HashMap::from([(100, new.clone())])), Ok(1));
+        let val = Bank::from_data::<LE>(&val.to_data::<LE>());
+        let wems = val.wems();
+        assert_eq!(wems[0], (100, &new[..]));
+        assert_eq!(wems[1].1, &wem(2, 20)[..]);
+        // the moved wem keeps the alignment and the sound points at it
+        let entry = &val.index()[1];
+        assert_eq!(entry.offset % 16, 0);
+        let sound = &val.objects()[0];
+        assert_eq!(val.read_u32(&sound.data[16..]), entry.offset);
+        assert_eq!(val.read_u32(&sound.data[20..]), entry.size);
+    }
+
+    #[test]
+    fn replace_wems_checks_format() {
+        let mut val = bank::<LE>();
+        assert!(val.replace_wems(&HashMap::from([(100, wem(3, 10))])).is_err());
+        assert!(val.replace_wems(&HashMap::from([(300, wem(2, 10))])).is_err());
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::HashSet;
+use log::{info, warn};
+
+use super::{
+    pak::{IndexBuffer, VertexTypes, VertexUsage},
+    pak_alt::{HkShape, Mesh, Shape},
+    level_alt::Level,
+    types::{Crc, Vector3, Vector4},
+};
+
+const EPS: f32 = 1e-5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum CollisionKind {
+    /// hkpConvexVerticesShape (HkShape5)
+    Hull,
+    /// triangle mesh (HkShape6)
+    Mesh,
+}
+
+/// Builds a `Shape` from raw geometry, any fields that can't be derived from the geometry are copied from a template shape
+pub struct ShapeBuilder<'a> {
+    template: &'a Shape,
+}
+
+impl<'a> ShapeBuilder<'a> {
+    pub fn new(template: &'a Shape) -> Result<Self, String> {
+        if template.extra.is_some() {
+            return Err("Shapes with extra data can't be used as a collision template".to_string());
+        }
+        Ok(Self { template })
+    }
+
+    pub fn build(&self, kind: CollisionKind, vertices: &[Vector3], indices: &[u32]) -> Result<Shape, String> {
+        match kind {
+            CollisionKind::Hull => self.convex_hull(vertices),
+            CollisionKind::Mesh => self.triangle_mesh(vertices, indices),
+        }
+    }
+
+    pub fn convex_hull(&self, vertices: &[Vector3]) -> Result<Shape, String> {
+        let points = vertices.iter().map(|v| [v.x, v.y, v.z]).collect::<Vec<_>>();
+        let (hull, planes) = convex_hull(&points)?;
+
+        let template = self.template.hk_shapes.iter().find_map(|x| match x {
+            HkShape::HkShape5 { info, .. } => Some(info.clone()),
+            _ => None
+        });
+        let mut info = template.unwrap_or_default();
+        info.kind = 5;
+        (info.unk_0, info.unk_4) = aabb(&hull, &info.unk_0, &info.unk_4);
+
+        // vertices are stored transposed in blocks of 4 (xxxx yyyy zzzz), the last block is padded with the last vertex
+        let b_extra = (4 - hull.len() % 4) % 4;
+        let padded = hull.iter().chain(std::iter::repeat_n(hull.last().unwrap(), b_extra)).collect::<Vec<_>>();
+        let b = padded.chunks(4).flat_map(|block|
+            (0..3).flat_map(move |i| block.iter().map(move |v| v[i].to_bits()))
+        ).collect::<Vec<_>>();
+        let a = planes.iter().flat_map(|p| p.iter().map(|x| x.to_bits())).collect::<Vec<_>>();
+
+        Ok(self.shape(HkShape::HkShape5 { info, a, b, b_extra }))
+    }
+
+    pub fn triangle_mesh(&self, vertices: &[Vector3], indices: &[u32]) -> Result<Shape, String> {
+        if indices.is_empty() {
+            return Err("Can't build a collision mesh without triangles".to_string());
+        }
+        if !indices.len().is_multiple_of(3) {
+            return Err(format!("Triangle mesh indices must be a multiple of 3, got {}", indices.len()));
+        }
+        if vertices.len() > u16::MAX as usize {
+            return Err(format!("Too many vertices for a collision mesh ({})", vertices.len()));
+        }
+        if let Some(i) = indices.iter().find(|x| **x as usize >= vertices.len()) {
+            return Err(format!("Index {} is out of range for {} vertices", i, vertices.len()));
+        }
+        let points = vertices.iter().map(|v| [v.x, v.y, v.z]).collect::<Vec<_>>();
+
+        let template = self.template.hk_shapes.iter().find_map(|x| match x {
+            HkShape::HkShape6 { info, c, .. } => Some((info.clone(), c.first().cloned().unwrap_or_default())),
+            _ => None
+        });
+        let (mut info, material) = template.unwrap_or_default();
+        info.kind = 6;
+        (info.unk_0, info.unk_4) = aabb(&points, &info.unk_0, &info.unk_4);
+
+        let d = points.iter().flat_map(|p| p.iter().map(|x| x.to_bits())).collect::<Vec<_>>();
+        let e = indices.iter().map(|x| *x as u16).collect::<Vec<_>>();
+        let c = vec![material; indices.len() / 3];
+
+        Ok(self.shape(HkShape::HkShape6 { info, c, d, e }))
+    }
+
+    fn shape(&self, hk_shape: HkShape) -> Shape {
+        let mut info = self.template.info.clone();
+        info.hk_shape_num = 1;
+        Shape { info, extra: None, hk_shapes: vec![hk_shape] }
+    }
+}
+
+/// Replaces the collision of a mesh with a single shape generated from its own vertex data
+pub fn generate(level: &mut Level, mesh: &Crc, template: Option<Crc>, kind: CollisionKind) -> Result<(), String> {
+    let template = template.as_ref().unwrap_or(mesh);
+    let template = level.meshes.get(template).ok_or_else(|| format!("Unknown mesh {}", template.to_string()))?
+        .shapes.iter().find(|x| x.extra.is_none()).ok_or_else(|| format!("Mesh {} has no usable collision shapes", template.to_string()))?
+        .clone();
+    let target = level.meshes.get_mut(mesh).ok_or_else(|| format!("Unknown mesh {}", mesh.to_string()))?;
+    let (vertices, indices) = mesh_geometry(target)?;
+    let shape = ShapeBuilder::new(&template)?.build(kind, &vertices, &indices)?;
+    info!("Generated {:?} collision for {} from {} vertices", kind, mesh.to_string(), vertices.len());
+    target.shapes = vec![shape];
+    Ok(())
+}
+
+/// Collects the positions and triangle list indices of a mesh, vertex buffers are paired with the index buffer of the same index
+pub fn mesh_geometry(mesh: &Mesh) -> Result<(Vec<Vector3>, Vec<u32>), String> {
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    for (i, vbuff) in mesh.vertex_data.iter().enumerate() {
+        let Some(pos) = vbuff.vals.iter().find_map(|(usage, val)| (*usage == VertexUsage::Position).then_some(val)) else {
+            continue
+        };
+        let base = vertices.len() as u32;
+        match pos {
+            VertexTypes::Vector3(x, y, z) | VertexTypes::Vector4(x, y, z, _) => vertices.extend(
+                x.iter().zip(y).zip(z).map(|((x, y), z)| Vector3 { x: *x, y: *y, z: *z })
+            ),
+            _ => return Err(format!("Unsupported vertex position format {:?}", pos))
+        }
+        if let Some(ibuff) = mesh.index_data.get(i) {
+            indices.extend(match ibuff {
+                IndexBuffer::U16 { vals } => vals.iter().map(|x| *x as u32 + base).collect::<Vec<_>>(),
+                IndexBuffer::U32 { vals } => vals.iter().map(|x| *x + base).collect::<Vec<_>>(),
+            });
+        }
+    }
+    if indices.len() % 3 != 0 {
+        warn!("Index data is not a triangle list, dropping {} trailing indices", indices.len() % 3);
+        indices.truncate(indices.len() - indices.len() % 3);
+    }
+    Ok((vertices, indices))
+}
+
+/// hkpConvexVerticesShape & hkpMeshShape store the aabb half extents and center, keep the template w components
+fn aabb(points: &[[f32; 3]], half_extents: &Vector4, center: &Vector4) -> (Vector4, Vector4) {
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for p in points {
+        for i in 0..3 {
+            min[i] = min[i].min(p[i]);
+            max[i] = max[i].max(p[i]);
+        }
+    }
+    (
+        Vector4 { x: (max[0] - min[0]) / 2.0, y: (max[1] - min[1]) / 2.0, z: (max[2] - min[2]) / 2.0, w: half_extents.w },
+        Vector4 { x: (max[0] + min[0]) / 2.0, y: (max[1] + min[1]) / 2.0, z: (max[2] + min[2]) / 2.0, w: center.w },
+    )
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] { [a[0] - b[0], a[1] - b[1], a[2] - b[2]] }
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 { a[0] * b[0] + a[1] * b[1] + a[2] * b[2] }
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] { [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]] }
+
+fn plane(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> [f32; 4] {
+    let n = cross(sub(b, a), sub(c, a));
+    let l = dot(n, n).sqrt().max(f32::MIN_POSITIVE);
+    let n = [n[0] / l, n[1] / l, n[2] / l];
+    [n[0], n[1], n[2], -dot(n, a)]
+}
+
+fn dist(plane: &[f32; 4], p: [f32; 3]) -> f32 {
+    plane[0] * p[0] + plane[1] * p[1] + plane[2] * p[2] + plane[3]
+}
+
+/// Hull vertices and plane equations
+pub type Hull = (Vec<[f32; 3]>, Vec<[f32; 4]>);
+
+/// Incremental convex hull, returns the hull vertices and the outward facing plane equations (n, -n.p)
+pub fn convex_hull(points: &[[f32; 3]]) -> Result<Hull, String> {
+    if points.len() < 4 {
+        return Err(format!("Need at least 4 vertices to build a convex hull, got {}", points.len()));
+    }
+    if points.iter().flatten().any(|x| !x.is_finite()) {
+        return Err("Can't build a convex hull from non finite vertices".to_string());
+    }
+    let scale = points.iter().flat_map(|p| p.iter()).fold(1.0f32, |a, x| a.max(x.abs()));
+    let eps = EPS * scale;
+
+    // initial tetrahedron from extreme points
+    let i0 = (0..points.len()).min_by(|a, b| points[*a][0].total_cmp(&points[*b][0])).unwrap();
+    let i1 = (0..points.len()).max_by(|a, b| {
+        let (da, db) = (sub(points[*a], points[i0]), sub(points[*b], points[i0]));
+        dot(da, da).total_cmp(&dot(db, db))
+    }).unwrap();
+    let line = sub(points[i1], points[i0]);
+    let i2 = (0..points.len()).max_by(|a, b| {
+        let (ca, cb) = (cross(line, sub(points[*a], points[i0])), cross(line, sub(points[*b], points[i0])));
+        dot(ca, ca).total_cmp(&dot(cb, cb))
+    }).unwrap();
+    let base = plane(points[i0], points[i1], points[i2]);
+    let i3 = (0..points.len()).max_by(|a, b| dist(&base, points[*a]).abs().total_cmp(&dist(&base, points[*b]).abs())).unwrap();
+    if dist(&base, points[i3]).abs() <= eps {
+        return Err("Can't build a convex hull from flat geometry, all vertices are coplanar".to_string());
+    }
+
+    // orient the faces of the tetrahedron away from its centroid
+    let c = [i0, i1, i2, i3].iter().fold([0.0; 3], |c, i| [c[0] + points[*i][0] / 4.0, c[1] + points[*i][1] / 4.0, c[2] + points[*i][2] / 4.0]);
+    let mut faces = [[i0, i1, i2], [i0, i1, i3], [i1, i2, i3], [i2, i0, i3]].map(|[a, b, c_]| {
+        if dist(&plane(points[a], points[b], points[c_]), c) > 0.0 { [a, c_, b] } else { [a, b, c_] }
+    }).to_vec();
+
+    for (i, p) in points.iter().enumerate() {
+        if [i0, i1, i2, i3].contains(&i) { continue; }
+        let visible = faces.iter().map(|f| dist(&plane(points[f[0]], points[f[1]], points[f[2]]), *p) > eps).collect::<Vec<_>>();
+        if !visible.iter().any(|x| *x) { continue; }
+        let edges = zip_faces(&faces, &visible);
+        let horizon = edges.iter().filter(|(a, b)| !edges.contains(&(*b, *a))).cloned().collect::<Vec<_>>();
+        faces = faces.into_iter().zip(visible).filter_map(|(f, v)| (!v).then_some(f)).collect();
+        faces.extend(horizon.into_iter().map(|(a, b)| [a, b, i]));
+    }
+
+    let used = faces.iter().flatten().cloned().collect::<HashSet<_>>();
+    let mut used = used.into_iter().collect::<Vec<_>>();
+    used.sort_unstable();
+    let vertices = used.into_iter().map(|i| points[i]).collect::<Vec<_>>();
+
+    let mut planes: Vec<[f32; 4]> = vec![];
+    for f in &faces {
+        let p = plane(points[f[0]], points[f[1]], points[f[2]]);
+        if !planes.iter().any(|q| dot([p[0], p[1], p[2]], [q[0], q[1], q[2]]) > 1.0 - EPS && (p[3] - q[3]).abs() <= eps) {
+            planes.push(p);
+        }
+    }
+    Ok((vertices, planes))
+}
+
+fn zip_faces(faces: &[[usize; 3]], visible: &[bool]) -> HashSet<(usize, usize)> {
+    faces.iter().zip(visible).filter(|(_, v)| **v).flat_map(|(f, _)| [(f[0], f[1]), (f[1], f[2]), (f[2], f[0])]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube() -> Vec<Vector3> {
+        (0..8).map(|i| Vector3 { x: (i & 1) as f32, y: (i >> 1 & 1) as f32, z: (i >> 2 & 1) as f32 }).collect()
+    }
+
+    #[test]
+    fn hull_of_cube() {
+        let template = Shape::default();
+        let mut points = cube();
+        points.push(Vector3 { x: 0.5, y: 0.5, z: 0.5 });
+        let shape = ShapeBuilder::new(&template).unwrap().convex_hull(&points).unwrap();
+        let HkShape::HkShape5 { a, b, b_extra, .. } = &shape.hk_shapes[0] else { panic!("Expected a convex hull") };
+        assert_eq!(a.len(), 6 * 4);
+        assert_eq!(b.len(), 2 * 12);
+        assert_eq!(*b_extra, 0);
+    }
+
+    #[test]
+    fn degenerate_input_is_an_error() {
+        let template = Shape::default();
+        let builder = ShapeBuilder::new(&template).unwrap();
+        let points = cube();
+        assert!(builder.convex_hull(&points[..3]).is_err());
+        assert!(builder.convex_hull(&points[..4]).is_err());
+        assert!(builder.convex_hull(&vec![Vector3 { x: 1.0, y: 1.0, z: 1.0 }; 8]).is_err());
+        assert!(builder.triangle_mesh(&points, &[]).is_err());
+        assert!(builder.triangle_mesh(&points, &[0, 1]).is_err());
+        assert!(builder.triangle_mesh(&points, &[0, 1, 8]).is_err());
+        assert!(builder.triangle_mesh(&points, &[0, 1, 2]).is_ok());
+    }
+}
@@ -0,0 +1,129 @@
+use std::{collections::HashSet, fs, path::Path, sync::atomic::{AtomicUsize, Ordering}};
+use itertools::Itertools;
+use log::info;
+use rayon::prelude::*;
+
+use super::types::{hash_update, STRING_LOOKUP};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Lit(Vec<u8>),
+    /// `*`, any word
+    Word,
+    /// `#`, a two digit number
+    Number,
+}
+
+fn parse_pattern(pattern: &str) -> Vec<Part> {
+    let mut parts = vec![];
+    let mut lit = vec![];
+    for c in pattern.bytes() {
+        match c {
+            b'*' | b'#' => {
+                if !lit.is_empty() {
+                    parts.push(Part::Lit(std::mem::take(&mut lit)));
+                }
+                parts.push(if c == b'*' { Part::Word } else { Part::Number });
+            },
+            c => lit.push(c),
+        }
+    }
+    if !lit.is_empty() {
+        parts.push(Part::Lit(lit));
+    }
+    parts
+}
+
+/// Patterns made from known names by swapping one `_` separated part for a placeholder,
+/// e.g. `CH_hum_archer_01` gives `*_hum_archer_01`, `CH_*_archer_01`, `CH_hum_*_01` and `CH_hum_archer_#`
+pub fn derive_patterns<'a>(names: impl IntoIterator<Item = &'a String>) -> Vec<String> {
+    let mut patterns = HashSet::new();
+    for name in names {
+        let parts = name.split('_').collect::<Vec<_>>();
+        if parts.len() < 2 {
+            continue;
+        }
+        for i in 0..parts.len() {
+            let mut parts = parts.clone();
+            parts[i] = if parts[i].len() == 2 && parts[i].bytes().all(|x| x.is_ascii_digit()) { "#" } else { "*" };
+            patterns.insert(parts.join("_"));
+        }
+    }
+    patterns.into_iter().sorted().collect()
+}
+
+/// Every `_` separated part of the known names
+pub fn name_parts<'a>(names: impl IntoIterator<Item = &'a String>) -> Vec<String> {
+    names.into_iter().flat_map(|x| x.split('_')).filter(|x| !x.is_empty())
+        .map(|x| x.to_lowercase()).unique().sorted().collect()
+}
+
+pub fn load_wordlist<P: AsRef<Path>>(path: P) -> Vec<String> {
+    fs::read_to_string(path).unwrap().lines().map(|x| x.trim().to_string()).filter(|x| !x.is_empty()).collect()
+}
+
+pub struct Cracker {
+    pub targets: HashSet<u32>,
+    words: Vec<Vec<u8>>,
+    numbers: Vec<Vec<u8>>,
+}
+
+impl Cracker {
+    pub fn new(targets: HashSet<u32>, words: &[String]) -> Self {
+        Self {
+            targets,
+            words: words.iter().map(|x| x.as_bytes().to_vec()).unique().collect(),
+            numbers: (0..100).map(|x| format!("{:02}", x).into_bytes()).collect(),
+        }
+    }
+
+    fn values<'a>(&'a self, part: &'a Part) -> &'a [Vec<u8>] {
+        match part {
+            Part::Lit(val) => std::slice::from_ref(val),
+            Part::Word => &self.words[..],
+            Part::Number => &self.numbers[..],
+        }
+    }
+
+    fn expand(&self, parts: &[Part], h: u32, buf: &mut Vec<u8>, hits: &mut Vec<(u32, String)>) {
+        let Some(part) = parts.first() else {
+            if self.targets.contains(&!h) {
+                hits.push((!h, String::from_utf8_lossy(buf).into_owned()));
+            }
+            return;
+        };
+        for val in self.values(part) {
+            let n = buf.len();
+            buf.extend(val);
+            self.expand(&parts[1..], hash_update(h, val), buf, hits);
+            buf.truncate(n);
+        }
+    }
+
+    /// Tries every pattern on the rayon threads. Returns the hits with the first name found for each key
+    pub fn run(&self, patterns: &[String]) -> Vec<(u32, String)> {
+        let patterns = patterns.iter().map(|x| parse_pattern(x)).collect::<Vec<_>>();
+        // each pattern is split on the values of its first placeholder, so a single pattern still uses every thread
+        let tasks = patterns.iter().flat_map(|parts| {
+            let i = parts.iter().position(|x| !matches!(x, Part::Lit(_))).unwrap_or(parts.len().saturating_sub(1));
+            (0..parts.get(i).map_or(0, |x| self.values(x).len())).map(move |j| (parts, i, j))
+        }).collect::<Vec<_>>();
+        let done = AtomicUsize::new(0);
+        let hits = tasks.par_iter().flat_map_iter(|(parts, i, j)| {
+            // everything before the placeholder is a literal
+            let mut buf = parts[..*i].iter().flat_map(|x| self.values(x)[0].clone()).collect::<Vec<_>>();
+            buf.extend(&self.values(&parts[*i])[*j]);
+            let h = hash_update(!0, &buf);
+            let mut found = vec![];
+            self.expand(&parts[i + 1..], h, &mut buf, &mut found);
+            let n = done.fetch_add(1, Ordering::Relaxed) + 1;
+            if n.is_multiple_of(10000) {
+                info!("{}/{} parts of the patterns", n, tasks.len());
+            }
+            found
+        }).collect::<Vec<_>>();
+        let hits = hits.into_iter().sorted().unique_by(|(k, _)| *k).collect::<Vec<_>>();
+        STRING_LOOKUP.lock().unwrap().extend(hits.iter().cloned());
+        hits
+    }
+}
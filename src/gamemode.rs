@@ -0,0 +1,406 @@
+use std::{cell::{OnceCell, RefCell}, collections::{BTreeMap, HashMap, HashSet}, sync::Arc};
+use itertools::Itertools;
+use log::warn;
+use serde_json::Value;
+
+use super::{
+    level_alt::Level,
+    lua_stuff,
+    types::{self, BaseTypes, Crc, GameObjs},
+};
+
+/// Something that is loaded per gamemode, objects are only included if their type has a GameModeMask field
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Asset {
+    Mesh(Crc),
+    Texture(Crc),
+    Animation(Crc),
+    Effect(Crc),
+    Object(u32),
+}
+
+impl Asset {
+    pub const CATEGORIES: [&'static str; 5] = ["meshes", "textures", "animations", "effects", "objects"];
+
+    pub fn category(&self) -> &'static str {
+        match self {
+            Self::Mesh(_) => "meshes",
+            Self::Texture(_) => "textures",
+            Self::Animation(_) => "animations",
+            Self::Effect(_) => "effects",
+            Self::Object(_) => "objects",
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Self::Mesh(key) | Self::Texture(key) | Self::Animation(key) | Self::Effect(key) => key.to_string(),
+            Self::Object(guid) => guid.to_string(),
+        }
+    }
+
+    /// Finds an asset by name, numbers are taken as object guids. Names can be prefixed with the category, e.g. `textures/name`
+    pub fn resolve(level: &Level, name: &str) -> Option<Self> {
+        let (category, name) = match name.split_once('/') {
+            Some((category, name)) if Self::CATEGORIES.contains(&category) => (Some(category), name),
+            _ => (None, name),
+        };
+        let key = Crc::from_string(name);
+        let found = [
+            level.meshes.contains_key(&key).then(|| Self::Mesh(key.clone())),
+            level.effects.contains_key(&key).then(|| Self::Effect(key.clone())),
+            level.animations.contains_key(&key).then(|| Self::Animation(key.clone())),
+            level.textures.contains_key(&key).then(|| Self::Texture(key.clone())),
+            name.parse::<u32>().ok().filter(|guid| level.game_objs().is_some_and(|objs| objs.find(*guid).is_some())).map(Self::Object),
+        ];
+        found.into_iter().flatten().find(|x| category.is_none_or(|c| x.category() == c))
+    }
+}
+
+/// A gamemode of a level, given by its mask bit, the guid of its gamemode object or its name
+#[derive(Debug, Clone)]
+pub enum Gamemode {
+    Index(usize),
+    Guid(u32),
+    Name(String),
+}
+
+impl Gamemode {
+    /// Mask bit of the gamemode in the level
+    pub fn bit(&self, level: &Level) -> usize {
+        match self {
+            Self::Index(i) if *i < level.animation_block_infos.len() => *i,
+            Self::Index(i) => panic!("The level only has {} gamemodes, not {}", level.animation_block_infos.len(), i + 1),
+            Self::Guid(guid) => level.animation_block_infos.iter().position(|x| x.guid == *guid)
+                .unwrap_or_else(|| panic!("No gamemode with guid {}", guid)),
+            Self::Name(name) => {
+                let key = Crc::from_string(name);
+                level.gamemodes.iter().position(|x| *x == key).unwrap_or_else(|| panic!("Unknown gamemode {}", name))
+            }
+        }
+    }
+}
+
+/// Name of a gamemode bit of the level, or its index if the level has no gamemode names
+pub fn name(level: &Level, bit: usize) -> String {
+    level.gamemodes.get(bit).map(|x| x.to_string()).unwrap_or_else(|| bit.to_string())
+}
+
+fn mask_field(objs: &GameObjs, obj: usize) -> Option<usize> {
+    objs.fields_of(&objs.obj_headers[obj].key).iter().position(|x| x.key.key() == GameObjs::GAMEMODEMASK_FIELD)
+}
+
+/// Index of every object by guid
+fn guid_index(level: &Level) -> HashMap<u32, usize> {
+    level.game_objs().map(|objs| (0..objs.objs.len()).filter_map(|i| objs.guid(i).map(|guid| (guid, i))).collect()).unwrap_or_default()
+}
+
+fn get_mask(level: &Level, guids: &HashMap<u32, usize>, asset: &Asset) -> Option<i32> {
+    match asset {
+        Asset::Mesh(key) => level.meshes.get(key).map(|x| x.info.gamemodemask),
+        Asset::Texture(key) => level.textures.get(key).map(|x| x.info().gamemodemask),
+        Asset::Animation(key) => level.animations.get(key).map(|x| x.info.gamemodemask),
+        Asset::Effect(key) => level.effects.get(key).map(|x| x.gamemodemask),
+        Asset::Object(guid) => {
+            let objs = level.game_objs()?;
+            let obj = *guids.get(guid)?;
+            match objs.objs[obj][mask_field(objs, obj)?] {
+                BaseTypes::Int(val) => Some(val),
+                _ => None
+            }
+        }
+    }
+}
+
+/// Sets or clears a gamemode bit on an asset and on the block a vals with the same key
+fn set_mask(level: &mut Level, guids: &HashMap<u32, usize>, asset: &Asset, bit: usize, enabled: bool) {
+    let update = |mask: &mut i32| if enabled { *mask |= 1 << bit } else { *mask &= !(1 << bit) };
+    match asset {
+        Asset::Mesh(key) => level.meshes.get_mut(key).into_iter().for_each(|x| update(&mut x.info.gamemodemask)),
+        Asset::Texture(key) => level.textures.get_mut(key).into_iter().for_each(|x| update(&mut x.info_mut().gamemodemask)),
+        Asset::Animation(key) => level.animations.get_mut(key).into_iter().for_each(|x| update(&mut x.info.gamemodemask)),
+        Asset::Effect(key) => level.effects.get_mut(key).into_iter().for_each(|x| update(&mut x.gamemodemask)),
+        Asset::Object(guid) => {
+            let Some(objs) = level.game_objs_mut() else { return };
+            let Some(obj) = guids.get(guid).cloned() else { return };
+            let Some(i) = mask_field(objs, obj) else { return };
+            if let BaseTypes::Int(val) = &mut objs.objs[obj][i] {
+                update(val);
+            }
+        }
+    }
+    if let Asset::Mesh(key) | Asset::Texture(key) | Asset::Animation(key) | Asset::Effect(key) = asset {
+        level.pak_vals_a.iter_mut().filter(|x| x.key == *key).for_each(|x| update(&mut x.gamemodemask));
+    }
+}
+
+/// Every asset that has the gamemode bit set
+pub fn assets(level: &Level, bit: usize) -> Vec<Asset> {
+    let mask = 1 << bit;
+    let mut vals = vec![];
+    vals.extend(level.meshes.iter().filter(|(_, x)| x.info.gamemodemask & mask != 0).map(|(k, _)| Asset::Mesh(k.clone())));
+    vals.extend(level.textures.iter().filter(|(_, x)| x.info().gamemodemask & mask != 0).map(|(k, _)| Asset::Texture(k.clone())));
+    vals.extend(level.animations.iter().filter(|(_, x)| x.info.gamemodemask & mask != 0).map(|(k, _)| Asset::Animation(k.clone())));
+    vals.extend(level.effects.iter().filter(|(_, x)| x.gamemodemask & mask != 0).map(|(k, _)| Asset::Effect(k.clone())));
+    if let Some(objs) = level.game_objs() {
+        vals.extend((0..objs.objs.len()).filter_map(|i| match objs.objs[i].get(mask_field(objs, i)?) {
+            Some(BaseTypes::Int(val)) if val & mask != 0 => objs.guid(i).map(Asset::Object),
+            _ => None
+        }));
+    }
+    vals.sort();
+    vals
+}
+
+/// Assets grouped by category, for printing
+pub fn group(assets: &[Asset]) -> BTreeMap<&'static str, Vec<String>> {
+    let mut vals = Asset::CATEGORIES.iter().map(|x| (*x, vec![])).collect::<BTreeMap<_, _>>();
+    for asset in assets {
+        vals.get_mut(asset.category()).unwrap().push(asset.name());
+    }
+    vals.values_mut().for_each(|x| x.sort());
+    vals
+}
+
+/// Animations listed by the ANM_ animation table scripts of a level, a table is only evaluated once something names it
+struct AnimTables {
+    names: HashSet<Crc>,
+    scripts: OnceCell<Arc<HashMap<Crc, Vec<u8>>>>,
+    tables: RefCell<HashMap<Crc, Vec<Crc>>>,
+}
+
+impl AnimTables {
+    fn new(level: &Level) -> Self {
+        let names = lua_scripts(level).map(|(name, _)| name).filter(|x| x.starts_with("ANM_")).map(Crc::from_string).collect();
+        Self { names, scripts: OnceCell::new(), tables: RefCell::new(HashMap::new()) }
+    }
+
+    fn get(&self, level: &Level, key: &Crc) -> Vec<Crc> {
+        if !self.names.contains(key) {
+            return vec![];
+        }
+        if let Some(val) = self.tables.borrow().get(key) {
+            return val.clone();
+        }
+        let scripts = self.scripts.get_or_init(|| {
+            let lua = lua_stuff::LuaCompiler::new().unwrap();
+            Arc::new(lua_scripts(level).map(|(name, val)| (Crc::from_string(name), lua.convert(&val.data, "L4808").unwrap())).collect())
+        });
+        let vals = lua_stuff::load_anim(scripts.clone(), key.to_string()).into_values().flat_map(|val| match val {
+            Value::String(val) => vec![val],
+            Value::Array(vals) => vals.into_iter().filter_map(|x| x.as_str().map(|x| x.to_string())).collect(),
+            val => {
+                warn!("Unexpected value {} in animation table {}", val, key.to_string());
+                vec![]
+            }
+        }).map(|x| Crc::from_string(&x)).collect::<Vec<_>>();
+        self.tables.borrow_mut().insert(key.clone(), vals.clone());
+        vals
+    }
+}
+
+fn lua_scripts(level: &Level) -> impl Iterator<Item = (&str, &types::Lua)> {
+    level.sub_blocks1.blocks.iter().filter_map(|x| match x {
+        types::SubBlock::Lua(val) => Some((val.name.strip_suffix(".lua").unwrap_or(&val.name), val)),
+        _ => None
+    })
+}
+
+/// Lookups for the dependencies of assets, built once per level so a closure stays linear in the number of assets
+pub struct Deps<'a> {
+    level: &'a Level,
+    guids: HashMap<u32, usize>,
+    layers: HashMap<u32, Vec<u32>>,
+    anim_tables: AnimTables,
+}
+
+impl<'a> Deps<'a> {
+    pub fn new(level: &'a Level) -> Self {
+        let guids = guid_index(level);
+        let mut layers: HashMap<u32, Vec<u32>> = HashMap::new();
+        if let Some(objs) = level.game_objs() {
+            for (guid, i) in &guids {
+                layers.entry(objs.obj_headers[*i].layer).or_default().push(*guid);
+            }
+        }
+        Self { level, guids, layers, anim_tables: AnimTables::new(level) }
+    }
+
+    /// Assets named by the crc, string and list fields of some objects, animation tables named by them add their animations
+    fn referenced<'b>(&self, fields: impl Iterator<Item = &'b BaseTypes>) -> Vec<Asset> {
+        let level = self.level;
+        let mut keys = vec![];
+        for field in fields {
+            match field {
+                BaseTypes::CRC(val) => keys.push(val.clone()),
+                BaseTypes::CRCList(vals) => keys.extend(vals.iter().cloned()),
+                BaseTypes::String(val) if !val.is_empty() => keys.push(Crc::from_string(val)),
+                BaseTypes::StringList(vals) => keys.extend(vals.iter().map(|x| Crc::from_string(x))),
+                _ => ()
+            }
+        }
+        let tables = keys.iter().flat_map(|key| self.anim_tables.get(level, key)).collect::<Vec<_>>();
+        keys.extend(tables);
+        keys.into_iter().filter(|x| x.key() != 0).flat_map(|key| [
+            level.meshes.contains_key(&key).then(|| Asset::Mesh(key.clone())),
+            level.textures.contains_key(&key).then(|| Asset::Texture(key.clone())),
+            level.animations.contains_key(&key).then(|| Asset::Animation(key.clone())),
+            level.effects.contains_key(&key).then(|| Asset::Effect(key.clone())),
+        ]).flatten().collect()
+    }
+
+    /// Direct dependencies, textures of a mesh's materials, assets named by effect or object fields,
+    /// the animations of the animation tables they name, and the objects an object references or holds as a layer
+    pub fn dependencies(&self, asset: &Asset) -> Vec<Asset> {
+        let level = self.level;
+        match asset {
+            Asset::Mesh(key) => level.meshes.get(key).map(|mesh| mesh.mats.iter().flat_map(|mat| {
+                let base = mat.base();
+                [
+                    &base.tex_2, &base.tex_3, &base.tex_4, &base.tex_5, &base.tex_6, &base.tex_7, &base.tex_8, &base.tex_9,
+                    &base.tex_10, &base.tex_11, &base.tex_12, &base.tex_13, &base.tex_14, &base.tex_15, &base.tex_16, &base.tex_17,
+                ].into_iter().filter(|x| level.textures.contains_key(*x)).map(|x| Asset::Texture(x.clone())).collect::<Vec<_>>()
+            }).unique().collect()).unwrap_or_default(),
+            Asset::Texture(_) | Asset::Animation(_) => vec![],
+            Asset::Effect(key) => level.effects.get(key).map(|objs| self.referenced(objs.objs.iter().flatten())).unwrap_or_default(),
+            Asset::Object(guid) => {
+                let Some(objs) = level.game_objs() else { return vec![] };
+                let Some(obj) = self.guids.get(guid).cloned() else { return vec![] };
+                let mut vals = self.referenced(objs.objs[obj].iter());
+                for field in &objs.objs[obj] {
+                    match field {
+                        BaseTypes::GUID(val) if *val != 0 && *val != *guid => vals.push(Asset::Object(*val)),
+                        BaseTypes::ObjectList(guids) => vals.extend(guids.iter().map(|x| Asset::Object(*x))),
+                        _ => ()
+                    }
+                }
+                vals.extend(self.layers.get(guid).into_iter().flatten().map(|x| Asset::Object(*x)));
+                vals
+            }
+        }
+    }
+
+    fn mask(&self, asset: &Asset) -> Option<i32> {
+        get_mask(self.level, &self.guids, asset)
+    }
+
+    /// An asset and everything it depends on, transitively
+    pub fn closure(&self, roots: impl IntoIterator<Item = Asset>) -> HashSet<Asset> {
+        self.closure_without(roots, None)
+    }
+
+    fn closure_without(&self, roots: impl IntoIterator<Item = Asset>, skip: Option<&Asset>) -> HashSet<Asset> {
+        let mut found = skip.into_iter().cloned().collect::<HashSet<_>>();
+        let mut queue = roots.into_iter().collect::<Vec<_>>();
+        while let Some(asset) = queue.pop() {
+            if self.mask(&asset).is_none() && !matches!(asset, Asset::Object(_)) {
+                continue;
+            }
+            if found.insert(asset.clone()) {
+                queue.extend(self.dependencies(&asset));
+            }
+        }
+        if let Some(skip) = skip {
+            found.remove(skip);
+        }
+        found
+    }
+}
+
+/// Adds an asset and its dependencies to a gamemode, returns the assets that weren't in it yet
+pub fn add(level: &mut Level, bit: usize, asset: &Asset) -> Vec<Asset> {
+    assert!(bit < level.animation_block_infos.len(), "Level has no gamemode {}", bit);
+    let deps = Deps::new(level);
+    let mut changed = deps.closure([asset.clone()]).into_iter()
+        .filter(|x| deps.mask(x).is_some_and(|mask| mask & (1 << bit) == 0))
+        .collect::<Vec<_>>();
+    changed.sort();
+    let guids = deps.guids;
+    for asset in &changed {
+        set_mask(level, &guids, asset, bit, true);
+    }
+    changed
+}
+
+/// Removes an asset from a gamemode along with the dependencies that nothing else left in the gamemode uses,
+/// returns the assets that were removed
+pub fn remove(level: &mut Level, bit: usize, asset: &Asset) -> Vec<Asset> {
+    assert!(bit < level.animation_block_infos.len(), "Level has no gamemode {}", bit);
+    let deps = Deps::new(level);
+    let targets = deps.closure([asset.clone()]);
+    let kept = deps.closure_without(assets(level, bit).into_iter().filter(|x| !targets.contains(x)), Some(asset));
+    let mut changed = targets.into_iter()
+        .filter(|x| !kept.contains(x) && deps.mask(x).is_some_and(|mask| mask & (1 << bit) != 0))
+        .collect::<Vec<_>>();
+    changed.sort();
+    let guids = deps.guids;
+    for asset in &changed {
+        set_mask(level, &guids, asset, bit, false);
+    }
+    changed
+}
+
+/// Number of assets per category in every gamemode
+pub fn summary(level: &Level) -> Vec<(usize, u32, HashMap<&'static str, usize>)> {
+    (0..level.animation_block_infos.len()).map(|i|
+        (i, level.animation_block_infos[i].guid, assets(level, i).iter().map(|x| x.category()).counts())
+    ).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pak::AnimationBlockInfo, pak_alt::Animation, types::{GameObjsObjHeader, GameObjsTypeField, GameObjsTypeHeader}};
+    use super::*;
+
+    /// Objects given as (guid, layer, script)
+    fn level(objs: &[(u32, u32, &str)]) -> Level {
+        let field = |name: &str, kind: &str, offset| GameObjsTypeField { key: name.into(), kind: kind.into(), offset };
+        let objs = GameObjs {
+            types: vec![GameObjsTypeHeader { key: "Obj".into(), size: 3, fields: 3 }],
+            type_fields: vec![vec![field("GUID", "GUID", 0), field("GameModeMask", "Int", 4), field("AnimationScript", "String", 8)]],
+            type_field_lookup: HashMap::from([(Crc::from("Obj").key(), 0)]),
+            obj_headers: objs.iter().map(|(_, layer, _)| GameObjsObjHeader { key: "Obj".into(), layer: *layer, ..Default::default() }).collect(),
+            objs: objs.iter().map(|(guid, _, script)| vec![BaseTypes::GUID(*guid), BaseTypes::Int(0), BaseTypes::String(script.to_string())]).collect(),
+            ..Default::default()
+        };
+        let mut level = Level::default();
+        level.sub_blocks1.blocks.push(types::SubBlock::GameObjs(objs));
+        level.animations.extend(["walk", "run", "idle"].map(|x| (x.into(), Animation::default())));
+        level.animation_block_infos.push(AnimationBlockInfo::default());
+        level
+    }
+
+    #[test]
+    fn closure_follows_layers_and_animation_tables() {
+        let level = level(&[(1, 0, "ANM_test"), (2, 1, ""), (3, 2, ""), (4, 0, "")]);
+        let deps = Deps::new(&level);
+        deps.anim_tables.tables.borrow_mut().insert("ANM_test".into(), vec!["walk".into(), "run".into(), "missing".into()]);
+        let mut found = deps.closure([Asset::Object(1)]).into_iter().collect::<Vec<_>>();
+        found.sort();
+        let mut expected = vec![Asset::Object(1), Asset::Object(2), Asset::Object(3)];
+        // the table is only used for names of ANM_ scripts in the level
+        assert_eq!(found, expected);
+
+        let mut deps = deps;
+        deps.anim_tables.names.insert("ANM_test".into());
+        let mut found = deps.closure([Asset::Object(1)]).into_iter().collect::<Vec<_>>();
+        found.sort();
+        expected.extend([Asset::Animation("walk".into()), Asset::Animation("run".into())]);
+        expected.sort();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let mut level = level(&[(1, 0, "walk"), (2, 1, "run"), (4, 0, "run")]);
+        let mut added = add(&mut level, 0, &Asset::Object(1));
+        added.sort();
+        assert_eq!(added, vec![Asset::Animation("run".into()), Asset::Animation("walk".into()), Asset::Object(1), Asset::Object(2)]);
+        assert_eq!(assets(&level, 0).len(), 4);
+        add(&mut level, 0, &Asset::Object(4));
+        // run is still used by 4
+        let removed = remove(&mut level, 0, &Asset::Object(1));
+        assert_eq!(removed, vec![Asset::Animation("walk".into()), Asset::Object(1), Asset::Object(2)]);
+        assert_eq!(assets(&level, 0), vec![Asset::Animation("run".into()), Asset::Object(4)]);
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::{HashMap, HashSet};
+use itertools::Itertools;
+use log::info;
+
+use super::{
+    level_alt::Level,
+    types::{BaseTypes, GameObjs},
+};
+
+/// Every guid in use across a level, the level objects and all the effects
+#[derive(Debug, Default, Clone)]
+pub struct GuidRegistry {
+    used: HashSet<u32>,
+    next: u32,
+}
+
+impl GuidRegistry {
+    pub fn new(level: &Level) -> Self {
+        let mut val = Self::default();
+        level.game_objs().into_iter().chain(level.effects.values()).for_each(|objs| val.register(objs));
+        val
+    }
+
+    /// Marks every GUID typed field in the objects as used
+    pub fn register(&mut self, objs: &GameObjs) {
+        for fields in &objs.objs {
+            for field in fields {
+                if let BaseTypes::GUID(guid) = field {
+                    self.used.insert(*guid);
+                }
+            }
+        }
+        // saturates at u32::MAX, which alloc then reports as used up
+        self.next = self.next.max(self.used.iter().max().map(|x| x.saturating_add(1)).unwrap_or(1));
+    }
+
+    pub fn contains(&self, guid: u32) -> bool {
+        guid == 0 || self.used.contains(&guid)
+    }
+
+    /// The next unused guid above the highest one registered
+    pub fn alloc(&mut self) -> Result<u32, String> {
+        while self.contains(self.next) {
+            self.next = self.next.checked_add(1).ok_or("No free guids left above the highest one in use")?;
+        }
+        self.used.insert(self.next);
+        Ok(self.next)
+    }
+
+    /// Gives every object whose guid is already used a fresh one and rewrites the GUID and ObjectList references to it,
+    /// returns the old -> new mapping
+    pub fn remap(&mut self, objs: &mut GameObjs) -> Result<HashMap<u32, u32>, String> {
+        let mut map = HashMap::new();
+        for i in 0..objs.objs.len() {
+            if let Some(guid) = objs.guid(i) {
+                if self.contains(guid) {
+                    map.insert(guid, self.alloc()?);
+                } else {
+                    self.used.insert(guid);
+                }
+            }
+        }
+        for fields in objs.objs.iter_mut() {
+            remap_fields(fields, &map);
+        }
+        Ok(map)
+    }
+}
+
+pub fn remap_fields(fields: &mut [BaseTypes], map: &HashMap<u32, u32>) {
+    for field in fields.iter_mut() {
+        match field {
+            BaseTypes::GUID(guid) => if let Some(new) = map.get(guid) { *guid = *new; },
+            BaseTypes::ObjectList(guids) => guids.iter_mut().for_each(|guid| if let Some(new) = map.get(guid) { *guid = *new; }),
+            _ => ()
+        }
+    }
+}
+
+/// Indices of an object and every object it references through GUID and ObjectList fields
+pub fn object_tree(objs: &GameObjs, guid: u32) -> Vec<usize> {
+    let lookup = (0..objs.objs.len()).filter_map(|i| objs.guid(i).map(|guid| (guid, i))).collect::<HashMap<_, _>>();
+    let mut found = vec![];
+    let mut seen = HashSet::new();
+    let mut queue = vec![guid];
+    while let Some(guid) = queue.pop() {
+        let Some(i) = lookup.get(&guid).cloned() else { continue };
+        if !seen.insert(i) { continue; }
+        found.push(i);
+        for field in &objs.objs[i] {
+            match field {
+                BaseTypes::GUID(guid) => queue.push(*guid),
+                BaseTypes::ObjectList(guids) => queue.extend(guids),
+                _ => ()
+            }
+        }
+    }
+    found
+}
+
+/// Copies an object and everything it references from src into dst, remapping guids that are already used in the destination level.
+/// Layers of the copied objects are kept when the destination has them, otherwise they are replaced by `layer`
+pub fn import_objects(dst: &mut Level, src: &GameObjs, guid: u32, layer: Option<u32>) -> Result<HashMap<u32, u32>, String> {
+    let mut registry = GuidRegistry::new(dst);
+    let objs = dst.game_objs_mut().ok_or("Level has no objects")?;
+    import_into(objs, &mut registry, src, guid, layer)
+}
+
+fn import_into(objs: &mut GameObjs, registry: &mut GuidRegistry, src: &GameObjs, guid: u32, layer: Option<u32>) -> Result<HashMap<u32, u32>, String> {
+    let mut subset = src.clone();
+    let tree = object_tree(src, guid);
+    if tree.is_empty() {
+        return Err(format!("No object with guid {}", guid));
+    }
+    subset.obj_headers = tree.iter().map(|i| src.obj_headers[*i].clone()).collect();
+    subset.objs = tree.iter().map(|i| src.objs[*i].clone()).collect();
+
+    for kind in subset.obj_headers.iter().map(|x| x.key.clone()).unique() {
+        objs.import_type(src, &kind)?;
+    }
+
+    let layers = (0..objs.objs.len()).filter_map(|i| objs.guid(i))
+        .chain(subset.objs.iter().enumerate().filter_map(|(i, _)| subset.guid(i)))
+        .collect::<HashSet<_>>();
+    let map = registry.remap(&mut subset)?;
+    for header in subset.obj_headers.iter_mut() {
+        header.layer = match map.get(&header.layer) {
+            Some(new) => *new,
+            None if header.layer == 0 || layers.contains(&header.layer) => header.layer,
+            None => layer.ok_or_else(|| format!("Layer {} of the imported objects isn't in the destination level, choose one with --layer", header.layer))?,
+        };
+    }
+    info!("Importing {} objects, {} guids remapped", subset.objs.len(), map.len());
+    let converted = subset.obj_headers.iter().zip(&subset.objs)
+        .map(|(header, fields)| (header.clone(), objs.convert_fields(&subset, &header.key, fields)))
+        .collect::<Vec<_>>();
+    objs.insert_objects(converted);
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use crate::read_write::{PathStuff, Reader, Writer};
+    use super::*;
+
+    /// Objects of a single type with a guid and a list of referenced guids, given as (guid, layer, refs)
+    fn objects(name: &str, objs: &[(u32, u32, &[u32])]) -> GameObjs {
+        let val = json!({
+            "gamemodemask": -1,
+            "types": [{
+                "name": "Obj",
+                "size": 2,
+                "fields": [{ "name": "GUID", "type": "GUID", "offset": 0 }, { "name": "Refs", "type": "ObjectList", "offset": 4 }]
+            }],
+            "objs": objs.iter().map(|(guid, layer, refs)| json!({
+                "type": "Obj", "layer": layer, "fields": { "GUID": guid, "Refs": refs }
+            })).collect::<Vec<Value>>(),
+        });
+        let path = std::env::temp_dir().join(format!("lotrc_test_{}_guids_{}", std::process::id(), name));
+        Writer::new(&path, false).join("objs").with_extension("json").write(&serde_json::to_vec(&val).unwrap());
+        GameObjs::from_file(Reader::new(&path).join("objs"), &[])
+    }
+
+    fn registry(objs: &GameObjs) -> GuidRegistry {
+        let mut val = GuidRegistry::default();
+        val.register(objs);
+        val
+    }
+
+    #[test]
+    fn alloc_overflow() {
+        let mut val = registry(&objects("overflow", &[(0xFFFFFFFE, 0, &[])]));
+        assert_eq!(val.alloc(), Ok(0xFFFFFFFF));
+        assert!(val.alloc().is_err());
+        assert!(registry(&objects("max", &[(0xFFFFFFFF, 0, &[])])).alloc().is_err());
+    }
+
+    #[test]
+    fn import_layers() {
+        // 10 is a layer in both levels, 20 only in the source, and 5 sits on the imported object 3 which collides in the destination
+        let src = objects("src", &[(10, 0, &[]), (20, 0, &[]), (1, 10, &[2, 3, 5]), (2, 20, &[]), (3, 0, &[]), (5, 3, &[])]);
+        let mut dst = objects("dst", &[(10, 0, &[]), (30, 0, &[]), (3, 10, &[])]);
+
+        assert!(import_into(&mut dst.clone(), &mut registry(&dst), &src, 1, None).is_err());
+        let mut used = registry(&dst);
+        let map = import_into(&mut dst, &mut used, &src, 1, Some(30)).unwrap();
+        assert_eq!(map, HashMap::from([(3, 31)]));
+        assert_eq!(dst.objs.len(), 7);
+        let layer = |guid: u32| dst.obj_headers[dst.find(guid).unwrap()].layer;
+        assert_eq!(layer(1), 10);
+        assert_eq!(layer(2), 30);
+        assert_eq!(layer(31), 0);
+        assert_eq!(layer(5), 31);
+        assert_eq!(layer(3), 10);
+    }
+}
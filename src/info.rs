@@ -0,0 +1,275 @@
+use std::{fs::File, io::Read, path::Path};
+use indexmap::IndexMap;
+use serde::Serialize;
+use zerocopy::LE;
+
+use super::{
+    lazy::LazyLevel,
+    level_alt::Level,
+    level_info::LevelInfo,
+    pak,
+    strings,
+    types::{Crc, GameObjs, SubBlock, SubBlocks},
+};
+
+/// Gamemode masks are 32 bit, so a level or level_info can have at most 32 gamemodes
+const GAMEMODE_LIMIT: usize = 32;
+
+/// Platform of a compiled file from the constant its header starts with
+pub fn platform<P: AsRef<Path>>(path: P, magic: u8) -> Option<String> {
+    let mut data = [0u8; 4];
+    File::open(path).ok()?.read_exact(&mut data).ok()?;
+    if data[0] == magic {
+        Some("PC (little endian)".to_string())
+    } else if data[3] == magic {
+        Some("PS3 / Xbox 360 (big endian)".to_string())
+    } else {
+        None
+    }
+}
+
+/// Engine limit of a table, the gamemode limit is the only one known so far
+fn limit(name: &str) -> Option<usize> {
+    (name == "animation_block_info").then_some(GAMEMODE_LIMIT)
+}
+
+fn size(val: usize) -> String {
+    match val {
+        0..=1023 => format!("{} B", val),
+        0x400..=0xFFFFF => format!("{:.1} KB", val as f64 / 1024.0),
+        _ => format!("{:.1} MB", val as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Table {
+    pub name: String,
+    pub count: usize,
+    /// None if the limit isn't known, not that the table is unlimited
+    pub limit: Option<usize>,
+}
+
+impl Table {
+    fn new(name: &str, count: usize) -> Self {
+        Self { name: name.to_string(), count, limit: limit(name) }
+    }
+
+    fn line(&self) -> String {
+        match self.limit {
+            Some(limit) if self.count > limit => format!("  {:28} {} / {} OVER LIMIT", self.name, self.count, limit),
+            Some(limit) => format!("  {:28} {} / {}", self.name, self.count, limit),
+            None => format!("  {:28} {} / ?", self.name, self.count),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Block {
+    pub name: String,
+    pub size: usize,
+    /// 0 if the block is stored uncompressed
+    pub size_comp: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SubBlockInfo {
+    pub block: usize,
+    pub name: String,
+    pub kind: &'static str,
+    pub size: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LevelReport {
+    /// Only known for compiled levels
+    pub platform: Option<String>,
+    pub pak_version: u32,
+    pub bin_version: u32,
+    pub tables: Vec<Table>,
+    /// Sizes from the pak header, so for a dump they're the sizes it was last compiled with
+    pub blocks: Vec<Block>,
+    pub sub_blocks: Vec<SubBlockInfo>,
+    pub languages: Vec<String>,
+    pub gamemodes: Vec<String>,
+    pub lua_scripts: usize,
+    pub crcs: usize,
+    pub unresolved_crcs: usize,
+}
+
+fn kind(block: &SubBlock) -> &'static str {
+    match block {
+        SubBlock::LangStrings(_) => "LangStrings",
+        SubBlock::Data(_) => "Data",
+        SubBlock::Spray(_) => "Spray",
+        SubBlock::Crowd(_) => "Crowd",
+        SubBlock::GameObjs(_) => "GameObjs",
+        SubBlock::AtlasUV(_) => "AtlasUV",
+        SubBlock::Lua(_) => "Lua",
+        SubBlock::SSA(_) => "SSA",
+    }
+}
+
+fn languages(blocks: &[&SubBlocks]) -> Vec<String> {
+    blocks.iter().flat_map(|x| x.block_headers.iter().zip(&x.blocks))
+        .filter(|(_, block)| matches!(block, SubBlock::LangStrings(_)))
+        .map(|(header, _)| header.key.to_string()).collect()
+}
+
+fn tables(header: &pak::Header, counts: &[(&str, usize)]) -> Vec<Table> {
+    let header = serde_json::to_value(header).unwrap();
+    header.as_object().unwrap().iter().filter_map(|(k, v)| {
+        let name = k.strip_suffix("_num")?;
+        let count = counts.iter().find(|(x, _)| *x == name).map(|(_, x)| *x).unwrap_or(v.as_u64()? as usize);
+        (count != 0 || limit(name).is_some()).then(|| Table::new(name, count))
+    }).collect()
+}
+
+fn sub_blocks(blocks: [&SubBlocks; 2]) -> Vec<SubBlockInfo> {
+    blocks.into_iter().enumerate().flat_map(|(i, x)|
+        x.block_headers.iter().zip(&x.blocks).map(move |(header, block)| SubBlockInfo {
+            block: i + 1, name: header.key.to_string(), kind: kind(block), size: block.size::<LE>()
+        })
+    ).collect()
+}
+
+fn gamemodes(names: &[Crc], infos: &[pak::AnimationBlockInfo]) -> Vec<String> {
+    infos.iter().enumerate().map(|(i, x)| format!("{} ({})", names.get(i).map(|x| x.to_string()).unwrap_or_else(|| i.to_string()), x.guid)).collect()
+}
+
+fn objs(blocks: [&SubBlocks; 2]) -> impl Iterator<Item = &GameObjs> {
+    blocks.into_iter().flat_map(|x| &x.blocks).filter_map(|x| match x {
+        SubBlock::GameObjs(objs) => Some(objs),
+        _ => None,
+    })
+}
+
+impl LevelReport {
+    fn build(header: &pak::Header, bin_version: u32, platform: Option<String>, tables: Vec<Table>, blocks: [&SubBlocks; 2], gamemodes: Vec<String>, crcs: (usize, usize)) -> Self {
+        let sub_blocks = sub_blocks(blocks);
+        let lua_scripts = sub_blocks.iter().filter(|x| x.kind == "Lua").count();
+        Self {
+            platform,
+            pak_version: header.version,
+            bin_version,
+            tables,
+            blocks: vec![
+                Block { name: "block1".to_string(), size: header.block1_size as usize, size_comp: header.block1_size_comp as usize },
+                Block { name: "block2".to_string(), size: header.block2_size as usize, size_comp: header.block2_size_comp as usize },
+            ],
+            sub_blocks,
+            languages: languages(&blocks),
+            gamemodes,
+            lua_scripts,
+            crcs: crcs.0 + crcs.1,
+            unresolved_crcs: crcs.1,
+        }
+    }
+
+    /// Report of a loaded level, the header counts of a dump are from its last compile so the tables it holds are counted from it
+    pub fn new(level: &Level, platform: Option<String>) -> Self {
+        let tables = tables(&level.pak_header, &[
+            ("obja", level.objas.len()),
+            ("obj0", level.obj0s.len()),
+            ("mesh_info", level.meshes.len()),
+            ("texture_info", level.textures.len()),
+            ("animation_info", level.animations.len()),
+            ("effect_info", level.effects.len()),
+            ("gfx_block_info", level.gfx_blocks.len()),
+            ("animation_block_info", level.animation_block_infos.len()),
+        ]);
+        let blocks = [&level.sub_blocks1, &level.sub_blocks2];
+        let names = level.meshes.keys().chain(level.textures.keys()).chain(level.animations.keys()).chain(level.effects.keys())
+            .chain(level.gfx_blocks.keys()).chain(level.radiosity.keys()).chain(level.foliages.keys());
+        let crcs = strings::counts(names
+            .chain(blocks.iter().flat_map(|x| x.block_headers.iter().map(|x| &x.key)))
+            .chain(level.string_keys.vals.iter().map(|x| &x.key))
+            .chain(objs(blocks).chain(level.effects.values()).flat_map(strings::objs_crcs)));
+        Self::build(&level.pak_header, level.bin_header.version, platform, tables, blocks, gamemodes(&level.gamemodes, &level.animation_block_infos), crcs)
+    }
+
+    /// Report of a compiled level, only the headers, block1 and block2 are read
+    pub fn from_lazy(level: &LazyLevel, platform: Option<String>) -> Self {
+        let tables = tables(&level.pak_header, &[]);
+        let (sub_blocks1, sub_blocks2) = (level.sub_blocks(0), level.sub_blocks(1));
+        let blocks = [&sub_blocks1, &sub_blocks2];
+        let effects = level.effects();
+        let string_keys = level.string_keys();
+        let crcs = strings::counts(level.asset_handles.iter().map(|x| &x.key)
+            .chain(effects.keys())
+            .chain(blocks.iter().flat_map(|x| x.block_headers.iter().map(|x| &x.key)))
+            .chain(string_keys.vals.iter().map(|x| &x.key))
+            .chain(objs(blocks).chain(effects.values()).flat_map(strings::objs_crcs)));
+        Self::build(&level.pak_header, level.bin_header.version, platform, tables, blocks, gamemodes(&level.gamemodes, &level.animation_block_infos()), crcs)
+    }
+
+    pub fn text(&self) -> String {
+        let mut out = vec![
+            format!("platform     {}", self.platform.as_deref().unwrap_or("dump")),
+            format!("version      pak {}, bin {}", self.pak_version, self.bin_version),
+        ];
+        for block in &self.blocks {
+            out.push(match block.size_comp {
+                0 => format!("{:12} {} (uncompressed)", block.name, size(block.size)),
+                n => format!("{:12} {} ({} compressed)", block.name, size(block.size), size(n)),
+            });
+        }
+        out.push("tables (count / limit, ? where the limit isn't known)".to_string());
+        out.extend(self.tables.iter().map(Table::line));
+        out.push("sub blocks".to_string());
+        for block in &self.sub_blocks {
+            out.push(format!("  {} {:32} {:12} {}", block.block, block.name, block.kind, size(block.size)));
+        }
+        out.push(format!("languages    {}", self.languages.join(", ")));
+        out.push(format!("gamemodes    {}", self.gamemodes.join(", ")));
+        out.push(format!("lua scripts  {}", self.lua_scripts));
+        out.push(format!("crcs         {} ({} unresolved, {:.1}%)", self.crcs, self.unresolved_crcs,
+            if self.crcs == 0 { 0.0 } else { self.unresolved_crcs as f64 * 100.0 / self.crcs as f64 }));
+        out.join("\n")
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct LevelInfoReport {
+    /// Only known for a compiled level_info
+    pub platform: Option<String>,
+    pub dlc: u32,
+    pub tables: Vec<Table>,
+    /// Levels with the gamemodes they can be played in
+    pub levels: IndexMap<String, Vec<String>>,
+    pub gamemodes: Vec<String>,
+    pub languages: Vec<String>,
+}
+
+impl LevelInfoReport {
+    pub fn new(level_info: &LevelInfo, platform: Option<String>) -> Self {
+        let gamemodes = level_info.gamemodes.iter().map(|x| x.key.to_string()).collect::<Vec<_>>();
+        Self {
+            platform,
+            dlc: level_info.header.dlc,
+            tables: vec![
+                Table::new("levels", level_info.levels.len()),
+                Table { limit: Some(GAMEMODE_LIMIT), ..Table::new("gamemodes", gamemodes.len()) },
+            ],
+            levels: level_info.levels.iter().map(|x| (
+                x.name.as_str().to_string(),
+                (0..32).filter(|i| x.gamemodes & (1 << i) != 0).map(|i| gamemodes.get(i).cloned().unwrap_or_else(|| i.to_string())).collect()
+            )).collect(),
+            languages: languages(&[&level_info.locale_strings]),
+            gamemodes,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        let mut out = vec![
+            format!("platform     {}", self.platform.as_deref().unwrap_or("dump")),
+            format!("dlc          {}", self.dlc),
+            "tables (count / limit, ? where the limit isn't known)".to_string(),
+        ];
+        out.extend(self.tables.iter().map(Table::line));
+        out.push("levels".to_string());
+        out.extend(self.levels.iter().map(|(name, modes)| format!("  {:28} {}", name, modes.join(", "))));
+        out.push(format!("gamemodes    {}", self.gamemodes.join(", ")));
+        out.push(format!("languages    {}", self.languages.join(", ")));
+        out.join("\n")
+    }
+}
@@ -0,0 +1,216 @@
+use std::{cell::OnceCell, collections::HashMap, fs::File, path::Path, sync::OnceLock};
+use memmap2::Mmap;
+use zerocopy::{LE, BE};
+use log::info;
+
+use super::{
+    pak, bin, lua_stuff::LuaCompiler, pak_alt::Mesh,
+    level_info::LevelInfo,
+    types::{self, hash_string, Crc, CompressedBlock, GameObjs, OrderedData, OrderedDataVec, SubBlock, SubBlocks, SubBlocksBlockHeader, SubBlocksHeader},
+};
+
+/// Calls a byte order generic function with the order of the level
+macro_rules! ordered {
+    ($level:expr, $($f:ident)::+ ($($arg:expr),*)) => {
+        if $level.big_endian { $($f)::+::<BE>($($arg),*) } else { $($f)::+::<LE>($($arg),*) }
+    };
+}
+
+/// A compiled level that only reads the headers up front,
+/// the main blocks and assets are decompressed from the mapped files when first needed
+pub struct LazyLevel {
+    pub big_endian: bool,
+    pub bin_header: bin::Header,
+    pub pak_header: pak::Header,
+    pub asset_handles: Vec<bin::AssetHandle>,
+    /// Names of the gamemode bits, from the level_info the level was opened with
+    pub gamemodes: Vec<Crc>,
+    /// Added languages whose blocks are read as strings, from the level_info the level was opened with
+    pub languages: Vec<Crc>,
+    bin_data: Mmap,
+    pak_data: Mmap,
+    block1: OnceLock<Vec<u8>>,
+    block2: OnceLock<Vec<u8>>,
+    lua: OnceCell<LuaCompiler>,
+}
+
+impl LazyLevel {
+    pub fn open<P: AsRef<Path>>(path: P, level_info: Option<&LevelInfo>) -> Result<Self, String> {
+        let path = path.as_ref();
+        info!("Opening level data {:?}", path);
+        let map = |ext: &str| {
+            let path = path.with_extension(ext);
+            let file = File::open(&path).map_err(|e| format!("Could not open {:?}: {}", path, e))?;
+            // the files must not change while they're mapped
+            unsafe { Mmap::map(&file) }.map_err(|e| format!("Could not map {:?}: {}", path, e))
+        };
+        let pak_data = map("PAK")?;
+        let bin_data = map("BIN")?;
+        let big_endian = match (bin_data.first(), bin_data.get(3)) {
+            (Some(6), _) => false,
+            (_, Some(6)) => true,
+            _ => return Err(format!("Invalid level data {:?}", path)),
+        };
+        let sizes = if big_endian {
+            (bin::Header::size::<BE>(), pak::Header::size::<BE>())
+        } else {
+            (bin::Header::size::<LE>(), pak::Header::size::<LE>())
+        };
+        if bin_data.len() < sizes.0 || pak_data.len() < sizes.1 {
+            return Err(format!("Level data {:?} is too short for its headers", path));
+        }
+        let mut val = Self {
+            big_endian,
+            bin_header: Default::default(),
+            pak_header: Default::default(),
+            asset_handles: vec![],
+            gamemodes: vec![],
+            languages: vec![],
+            bin_data,
+            pak_data,
+            block1: OnceLock::new(),
+            block2: OnceLock::new(),
+            lua: OnceCell::new(),
+        };
+        val.bin_header = ordered!(val, OrderedData::from_bytes(&val.bin_data[..]));
+        val.pak_header = ordered!(val, OrderedData::from_bytes(&val.pak_data[..]));
+        let bin_strings = ordered!(val, types::Strings::from_data(&val.bin_data[..], val.bin_header.strings_offset as usize, val.bin_header.strings_num as usize));
+        let pak_strings = ordered!(val, types::Strings::from_data(&val.pak_data[..], val.pak_header.strings_offset as usize, val.pak_header.strings_num as usize));
+        types::update_strings(&bin_strings.strings);
+        types::update_strings(&pak_strings.strings);
+        val.asset_handles = ordered!(val, OrderedDataVec::from_bytes(&val.bin_data[val.bin_header.asset_handle_offset as usize..], val.bin_header.asset_handle_num as usize));
+        if let Some(level_info) = level_info {
+            val.gamemodes = level_info.level_gamemodes(path.file_stem().unwrap().to_str().unwrap(), val.pak_header.animation_block_info_num as usize);
+            val.languages = level_info.languages();
+        }
+        Ok(val)
+    }
+
+    /// The compiler for the lua sub blocks, made the first time one is read
+    fn lua(&self) -> &LuaCompiler {
+        self.lua.get_or_init(|| LuaCompiler::new().unwrap())
+    }
+
+    pub fn block1(&self) -> &[u8] {
+        self.block1.get_or_init(|| {
+            let header = &self.pak_header;
+            CompressedBlock::from_data(&self.pak_data[..], header.block1_size as usize, header.block1_size_comp as usize, header.block1_offset as usize).data
+        })
+    }
+
+    pub fn block2(&self) -> &[u8] {
+        self.block2.get_or_init(|| {
+            let header = &self.pak_header;
+            CompressedBlock::from_data(&self.pak_data[..], header.block2_size as usize, header.block2_size_comp as usize, header.block2_offset as usize).data
+        })
+    }
+
+    /// Data and sub blocks offset of block1 (0) or block2 (1)
+    fn sub_blocks_data(&self, block: usize) -> (&[u8], usize) {
+        match block {
+            0 => (self.block1(), self.pak_header.sub_blocks1_offset as usize),
+            _ => (self.block2(), self.pak_header.sub_blocks2_offset as usize),
+        }
+    }
+
+    pub fn sub_block_headers(&self, block: usize) -> Vec<SubBlocksBlockHeader> {
+        let (data, offset) = self.sub_blocks_data(block);
+        let header: SubBlocksHeader = ordered!(self, OrderedData::from_bytes(&data[offset..]));
+        ordered!(self, OrderedDataVec::from_bytes(&data[offset + ordered!(self, SubBlocksHeader::size())..], header.block_num as usize))
+    }
+
+    /// Parses a single sub block along with the index of its block, block2 is only decompressed if it isn't in block1
+    pub fn sub_block(&self, name: &Crc) -> Option<(usize, SubBlock)> {
+        let (block, header) = (0..2).find_map(|i| self.sub_block_headers(i).into_iter().find(|x| x.key == *name).map(|x| (i, x)))?;
+        let (data, offset) = self.sub_blocks_data(block);
+        let mut val = ordered!(self, SubBlock::from_data(&data[offset..], &header, self.lua()));
+        if self.languages.contains(&header.key) {
+            ordered!(self, SubBlock::read_language(&mut val, &header.key, &self.string_keys()));
+        }
+        Some((block, val))
+    }
+
+    /// Every sub block of block1 (0) or block2 (1)
+    pub fn sub_blocks(&self, block: usize) -> SubBlocks {
+        let (data, offset) = self.sub_blocks_data(block);
+        let mut val = ordered!(self, SubBlocks::from_data(data, offset, self.lua()));
+        ordered!(self, SubBlocks::read_languages(&mut val, &self.string_keys(), &self.languages));
+        val
+    }
+
+    /// The main level objects, without parsing any of the other sub blocks
+    pub fn game_objs(&self) -> Option<GameObjs> {
+        let header = self.sub_block_headers(0).into_iter().find(|x| x.key.key() == GameObjs::KEY)?;
+        let (data, offset) = self.sub_blocks_data(0);
+        Some(ordered!(self, GameObjs::from_data(&data[offset..], header.offset as usize, header.size as usize, -1)))
+    }
+
+    pub fn effects(&self) -> HashMap<Crc, GameObjs> {
+        let block1 = self.block1();
+        let infos: Vec<pak::EffectInfo> = ordered!(self, OrderedDataVec::from_bytes(&block1[self.pak_header.effect_info_offset as usize..], self.pak_header.effect_info_num as usize));
+        infos.into_iter().map(|info| (
+            info.key, ordered!(self, GameObjs::from_data(block1, info.offset as usize, info.size as usize, info.gamemodemask))
+        )).collect()
+    }
+
+    pub fn animation_block_infos(&self) -> Vec<pak::AnimationBlockInfo> {
+        ordered!(self, OrderedDataVec::from_bytes(&self.block1()[self.pak_header.animation_block_info_offset as usize..], self.pak_header.animation_block_info_num as usize))
+    }
+
+    pub fn string_keys(&self) -> types::StringKeys {
+        ordered!(self, types::StringKeys::from_data(self.block1(), self.pak_header.string_keys_offset as usize))
+    }
+
+    /// Decompresses a single asset from the bin
+    pub fn asset(&self, key: &Crc, kind: u32) -> Option<Vec<u8>> {
+        let info = self.asset_handles.iter().find(|x| x.key == *key && x.kind == kind)?;
+        Some(CompressedBlock::from_data(&self.bin_data[..], info.size as usize, info.size_comp as usize, info.offset as usize).data)
+    }
+
+    pub fn texture(&self, key: &Crc) -> Option<bin::Tex> {
+        let infos: Vec<pak::TextureInfo> = ordered!(self, OrderedDataVec::from_bytes(&self.block1()[self.pak_header.texture_info_offset as usize..], self.pak_header.texture_info_num as usize));
+        let mut info = infos.into_iter().find(|x| x.key == *key)?;
+        let data0 = self.asset(&info.asset_key, info.asset_type)?;
+        let data1 = self.asset(&Crc::Key(hash_string("*".as_bytes(), Some(info.asset_key.key()))), info.asset_type)?;
+        Some(ordered!(self, bin::Tex::from_data(&data0, &data1, &mut info)))
+    }
+
+    pub fn mesh(&self, key: &Crc) -> Option<Mesh> {
+        let block1 = self.block1();
+        let size = ordered!(self, pak::MeshInfo::size());
+        let offset = (0..self.pak_header.mesh_info_num as usize).map(|i| self.pak_header.mesh_info_offset as usize + i * size).find(|offset| {
+            let info: pak::MeshInfo = ordered!(self, OrderedData::from_bytes(&block1[*offset..]));
+            info.key == *key
+        })?;
+        let mut mesh = ordered!(self, Mesh::from_data(block1, offset));
+        if mesh.info.vbuff_num != 0 || mesh.info.ibuff_num != 0 {
+            let buffer = self.asset(&mesh.info.asset_key, mesh.info.asset_type)?;
+            let mut formats = HashMap::new();
+            let vertex_data = mesh.vbuffs.iter_mut().map(|info| ordered!(self, pak::VertexBuffer::from_data(&buffer[..], info, &mut formats))).collect::<Vec<_>>();
+            let index_data = mesh.ibuffs.iter().map(|info| ordered!(self, pak::IndexBuffer::from_data(&buffer[..], info))).collect::<Vec<_>>();
+            mesh.vertex_data.extend(vertex_data);
+            mesh.index_data.extend(index_data);
+        }
+        Some(mesh)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_errors() {
+        let path = std::env::temp_dir().join(format!("lotrc_test_{}_lazy", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        let level = path.join("Level");
+        let error = || LazyLevel::open(&level, None).err().unwrap();
+        assert!(error().starts_with("Could not open"));
+        std::fs::write(level.with_extension("PAK"), [0u8; 16]).unwrap();
+        std::fs::write(level.with_extension("BIN"), [0u8; 16]).unwrap();
+        assert!(error().starts_with("Invalid level data"));
+        std::fs::write(level.with_extension("BIN"), [6u8, 0, 0, 0]).unwrap();
+        assert!(error().contains("too short"));
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
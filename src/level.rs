@@ -7,6 +7,7 @@ use std::iter::zip;
 
 use super::{
     pak, bin, lua_stuff,
+    level_info::LevelInfo,
     types::{self, hash_string, OrderedData, OrderedDataVec}
 };
 
@@ -82,15 +83,17 @@ pub struct Level {
 }
 
 impl Level {
-    pub fn parse<P: AsRef<Path>>(path: P) -> Self {
+    /// Reads a compiled level, blocks of the languages of the `level_info` are read as strings
+    pub fn parse<P: AsRef<Path>>(path: P, level_info: Option<&LevelInfo>) -> Self {
         let path = path.as_ref();
         info!("Parsing level data {}", path.file_stem().unwrap().to_str().unwrap());   
         let pak_data = fs::read(path.with_extension("PAK")).unwrap();
         let bin_data = fs::read(path.with_extension("BIN")).unwrap();    
+        let languages = level_info.map(LevelInfo::languages).unwrap_or_default();
         if bin_data[0] == 6 {
-            Self::from_data::<LE>(&bin_data[..], &pak_data[..])
+            Self::from_data::<LE>(&bin_data[..], &pak_data[..], &languages)
         } else if bin_data[3] == 6 {
-            Self::from_data::<BE>(&bin_data[..], &pak_data[..])
+            Self::from_data::<BE>(&bin_data[..], &pak_data[..], &languages)
         } else {
             warn!("Invalid level data");
             Default::default()
@@ -104,7 +107,7 @@ impl Level {
         fs::write(path.with_extension("BIN"), bin).unwrap();
     }
 
-    pub fn from_data<O: ByteOrder + 'static>(bin_data: &[u8], pak_data: &[u8]) -> Self {
+    pub fn from_data<O: ByteOrder + 'static>(bin_data: &[u8], pak_data: &[u8], languages: &[types::Crc]) -> Self {
         let time = Instant::now();
         info!("extracting level");
 
@@ -175,6 +178,8 @@ impl Level {
         val.string_keys = types::StringKeys::from_data::<O>(&val.block1.data[..], val.pak_header.string_keys_offset as usize);
         val.sub_blocks1 = types::SubBlocks::from_data::<O>(&val.block1.data[..], val.pak_header.sub_blocks1_offset as usize, &lua);
         val.sub_blocks2 = types::SubBlocks::from_data::<O>(&val.block2.data[..], val.pak_header.sub_blocks2_offset as usize, &lua);
+        val.sub_blocks1.read_languages::<O>(&val.string_keys, languages);
+        val.sub_blocks2.read_languages::<O>(&val.string_keys, languages);
         val.block2_offsets = OrderedDataVec::from_bytes::<O>(&val.block2.data[val.pak_header.block2_offsets_offset as usize..], val.pak_header.block2_offsets_num as usize);
         info!("sub blocks extracted in {:?}", time.elapsed());
 
@@ -1,14 +1,15 @@
-use std::{any::TypeId, collections::HashMap, ffi::OsStr, fs, path::Path, sync::Arc};
+use std::{any::TypeId, collections::HashMap, fs, path::Path, sync::Arc};
 use itertools::Itertools;
+use rayon::prelude::*;
 use zerocopy::{ByteOrder, LE, BE};
 use log::{warn, info};
 use serde::{Serialize, Deserialize};
-use serde_json::to_vec_pretty;
 use std::time::Instant;
 use std::iter::zip;
 
 use super::{
     pak, bin, lua_stuff, pak_alt::*,
+    level_info::LevelInfo,
     types::{self, hash_string, GameObjs, OrderedData, OrderedDataVec, CompressedBlock, Crc},
     read_write::{Reader, Writer, PathStuff},
 };
@@ -30,6 +31,8 @@ pub struct Level {
     pub effects: HashMap<Crc, GameObjs>,
     pub pfield_infos: Vec<pak::PFieldInfo>,
     pub animation_block_infos: Vec<pak::AnimationBlockInfo>,
+    /// Names of the gamemode bits of the masks in this level, bit i is the gamemode of animation_block_infos[i]
+    pub gamemodes: Vec<Crc>,
     pub gfx_blocks: HashMap<Crc, Vec<u8>>,
 
     pub string_keys: types::StringKeys,
@@ -45,19 +48,25 @@ pub struct Level {
 }
 
 impl Level {
-    pub fn parse<P: AsRef<Path>>(path: P) -> Self {
+    /// Reads a compiled level, the names of its gamemodes and added languages are taken from the `level_info` if one is given
+    pub fn parse<P: AsRef<Path>>(path: P, level_info: Option<&LevelInfo>) -> Self {
         let path = path.as_ref();
         info!("Parsing level data {:?}", path);   
         let pak_data = fs::read(path.with_extension("PAK")).unwrap();
         let bin_data = fs::read(path.with_extension("BIN")).unwrap();
-        if bin_data[0] == 6 {
-            Self::from_data::<LE>(&bin_data[..], &pak_data[..])
+        let languages = level_info.map(LevelInfo::languages).unwrap_or_default();
+        let mut val: Self = if bin_data[0] == 6 {
+            Self::from_data::<LE>(&bin_data[..], &pak_data[..], &languages)
         } else if bin_data[3] == 6 {
-            Self::from_data::<BE>(&bin_data[..], &pak_data[..])
+            Self::from_data::<BE>(&bin_data[..], &pak_data[..], &languages)
         } else {
             warn!("Invalid level data");
             Default::default()
+        };
+        if let Some(level_info) = level_info {
+            val.gamemodes = level_info.level_gamemodes(path.file_stem().unwrap().to_str().unwrap(), val.animation_block_infos.len());
         }
+        val
     }
 
     pub fn dump<O: ByteOrder + 'static, P: AsRef<Path>>(&self, path: P) {
@@ -69,7 +78,23 @@ impl Level {
         // fs::write(path.with_extension("json"), serde_json::to_vec_pretty(&_infos).unwrap()).unwrap();
     }
 
-    pub fn from_data<O: ByteOrder + 'static>(bin_data: &[u8], pak_data: &[u8]) -> Self {
+    /// The main level objects (the "Level" block in sub_blocks1)
+    pub fn game_objs(&self) -> Option<&GameObjs> {
+        self.sub_blocks1.blocks.iter().find_map(|x| match x {
+            types::SubBlock::GameObjs(val) => Some(val),
+            _ => None
+        })
+    }
+
+    pub fn game_objs_mut(&mut self) -> Option<&mut GameObjs> {
+        self.sub_blocks1.blocks.iter_mut().find_map(|x| match x {
+            types::SubBlock::GameObjs(val) => Some(val),
+            _ => None
+        })
+    }
+
+    /// Sub blocks named in `languages` that aren't built in languages are read as strings
+    pub fn from_data<O: ByteOrder + 'static>(bin_data: &[u8], pak_data: &[u8], languages: &[Crc]) -> Self {
         let time = Instant::now();
         info!("extracting level");
 
@@ -79,11 +104,11 @@ impl Level {
         types::update_strings(&bin_strings.strings);
 
         let asset_handles: Vec<bin::AssetHandle> = OrderedDataVec::from_bytes::<O>(&bin_data[bin_header.asset_handle_offset as usize..], bin_header.asset_handle_num as usize);
-        let asset_data = HashMap::<(Crc, u32), Vec<u8>>::from_iter(
-            asset_handles.iter().map(|info| ((info.key.clone(), info.kind), types::CompressedBlock::from_data(bin_data, info.size as usize, info.size_comp as usize, info.offset as usize).data))
-        );
+        let asset_data = asset_handles.par_iter().map(|info|
+            ((info.key.clone(), info.kind), types::CompressedBlock::from_data(bin_data, info.size as usize, info.size_comp as usize, info.offset as usize).data)
+        ).collect::<HashMap<_, _>>();
 
-        let radiosity: HashMap<Crc, bin::Radiosity> = asset_data.iter().filter(|(key, _)| key.0.str().map(|x| x.ends_with("_radiosity")).unwrap_or(false)).map(|(key, data)| (
+        let radiosity: HashMap<Crc, bin::Radiosity> = asset_data.par_iter().filter(|(key, _)| key.0.str().map(|x| x.ends_with("_radiosity")).unwrap_or(false)).map(|(key, data)| (
             key.0.clone(), bin::Radiosity::from_data::<O>(&data[..], key.1)
         )).collect();
 
@@ -94,12 +119,14 @@ impl Level {
         types::update_strings(&pak_strings.strings);
         info!("headers in {:?}", time.elapsed());
 
-        let block2 = types::CompressedBlock::from_data(pak_data, pak_header.block2_size as usize, pak_header.block2_size_comp as usize, pak_header.block2_offset as usize).data;
-        let sub_blocks2 = types::SubBlocks::from_data::<O>(&block2[..], pak_header.sub_blocks2_offset as usize, &lua);
+        let (block2, block1) = rayon::join(
+            || types::CompressedBlock::from_data(pak_data, pak_header.block2_size as usize, pak_header.block2_size_comp as usize, pak_header.block2_offset as usize).data,
+            || types::CompressedBlock::from_data(pak_data, pak_header.block1_size as usize, pak_header.block1_size_comp as usize, pak_header.block1_offset as usize).data,
+        );
+        let mut sub_blocks2 = types::SubBlocks::from_data::<O>(&block2[..], pak_header.sub_blocks2_offset as usize, &lua);
         let block2_offsets = OrderedDataVec::from_bytes::<O>(&block2[pak_header.block2_offsets_offset as usize..], pak_header.block2_offsets_num as usize);
         info!("block2 parsed in {:?}", time.elapsed());
 
-        let block1 = types::CompressedBlock::from_data(pak_data, pak_header.block1_size as usize, pak_header.block1_size_comp as usize, pak_header.block1_offset as usize).data;
         info!("main blocks extracted in {:?}", time.elapsed());
 
         let objas = OrderedDataVec::from_bytes::<O>(&block1[pak_header.obja_offset as usize..], pak_header.obja_num as usize);
@@ -107,18 +134,23 @@ impl Level {
         let animation_block_infos: Vec<pak::AnimationBlockInfo> = OrderedDataVec::from_bytes::<O>(&block1[pak_header.animation_block_info_offset as usize..], pak_header.animation_block_info_num as usize);
         let pfield_infos = OrderedDataVec::from_bytes::<O>(&block1[pak_header.pfield_info_offset as usize..], pak_header.pfield_info_num as usize);
 
+        // each mesh gets its own vertex format cache, merged once they're all read
         let mut vertex_formats = HashMap::new();
-        let meshes = (0..pak_header.mesh_info_num as usize).map(|i| {
+        let meshes = (0..pak_header.mesh_info_num as usize).into_par_iter().map(|i| {
+            let mut formats = HashMap::new();
             let mut mesh = Mesh::from_data::<O>(&block1[..], pak_header.mesh_info_offset as usize + i * pak::MeshInfo::size::<O>());
             if mesh.info.vbuff_num != 0 || mesh.info.ibuff_num != 0 {
                 let buffer = asset_data.get(&(mesh.info.asset_key.clone(), mesh.info.asset_type)).unwrap();
-                mesh.vertex_data.extend(mesh.vbuffs.iter_mut().map(|info| pak::VertexBuffer::from_data::<O>(&buffer[..], info, &mut vertex_formats)));
+                mesh.vertex_data.extend(mesh.vbuffs.iter_mut().map(|info| pak::VertexBuffer::from_data::<O>(&buffer[..], info, &mut formats)));
                 mesh.index_data.extend(mesh.ibuffs.iter().map(|info| pak::IndexBuffer::from_data::<O>(&buffer[..], info)));    
             }
-            (mesh.info.key.clone(), mesh)
+            (mesh.info.key.clone(), mesh, formats)
+        }).collect::<Vec<_>>().into_iter().map(|(key, mesh, formats)| {
+            vertex_formats.extend(formats);
+            (key, mesh)
         }).collect::<HashMap<_, _>>();
 
-        let effects = <Vec<pak::EffectInfo> as OrderedDataVec>::from_bytes::<O>(&block1[pak_header.effect_info_offset as usize..], pak_header.effect_info_num as usize).into_iter().map(|info| (
+        let effects = <Vec<pak::EffectInfo> as OrderedDataVec>::from_bytes::<O>(&block1[pak_header.effect_info_offset as usize..], pak_header.effect_info_num as usize).into_par_iter().map(|info| (
             info.key, GameObjs::from_data::<O>(&block1[..], info.offset as usize, info.size as usize, info.gamemodemask)
         )).collect::<HashMap<_, _>>();
 
@@ -137,14 +169,14 @@ impl Level {
             ))
         }
 
-        let textures = <Vec<pak::TextureInfo> as OrderedDataVec>::from_bytes::<O>(&block1[pak_header.texture_info_offset as usize..], pak_header.texture_info_num as usize).into_iter().map(|mut info| {
+        let textures = <Vec<pak::TextureInfo> as OrderedDataVec>::from_bytes::<O>(&block1[pak_header.texture_info_offset as usize..], pak_header.texture_info_num as usize).into_par_iter().map(|mut info| {
             let data0 = &asset_data.get(&(info.asset_key.clone(), info.asset_type)).unwrap();
             let data1 = &asset_data.get(&(Crc::Key(hash_string("*".as_bytes(), Some(info.asset_key.key()))), info.asset_type)).unwrap();
             let key = info.key.clone();
             (key, bin::Tex::from_data::<O>(&data0, &data1, &mut info))
         }).collect::<HashMap<_, _>>();
 
-        let blocks = animation_block_infos.iter().map(|info| 
+        let blocks = animation_block_infos.par_iter().map(|info| 
             types::CompressedBlock::from_data(&pak_data[..], info.size as usize, info.size_comp as usize, info.offset as usize).data
         ).collect::<Vec<_>>();
         let mut offsets = blocks.iter().map(|_| 0usize).collect::<Vec<_>>();
@@ -155,8 +187,10 @@ impl Level {
 
         info!("items extracted in {:?}", time.elapsed());
 
-        let sub_blocks1 = types::SubBlocks::from_data::<O>(&block1[..], pak_header.sub_blocks1_offset as usize, &lua);
+        let mut sub_blocks1 = types::SubBlocks::from_data::<O>(&block1[..], pak_header.sub_blocks1_offset as usize, &lua);
         let string_keys = types::StringKeys::from_data::<O>(&block1[..], pak_header.string_keys_offset as usize);
+        sub_blocks1.read_languages::<O>(&string_keys, languages);
+        sub_blocks2.read_languages::<O>(&string_keys, languages);
         info!("sub blocks extracted in {:?}", time.elapsed());
 
         let pak_vals_a = OrderedDataVec::from_bytes::<O>(&pak_data[pak_header.block_a_offset as usize..], pak_header.block_a_num as usize);
@@ -177,6 +211,7 @@ impl Level {
             effects,
             pfield_infos,
             animation_block_infos,
+            gamemodes: Vec::new(),
             string_keys,
             sub_blocks1,
             sub_blocks2,
@@ -250,8 +285,9 @@ impl Level {
                 k
             }
         }
-        let texture_infos = self.textures.values().sorted_by_key(sort_texture).map(|tex| {
-            let (data0, data1) = tex.dump::<O>();
+        let texture_vals = self.textures.values().sorted_by_key(sort_texture).collect::<Vec<_>>()
+            .into_par_iter().map(|tex| (tex, tex.dump::<O>())).collect::<Vec<_>>();
+        let texture_infos = texture_vals.into_iter().map(|(tex, (data0, data1))| {
             if data1.len() == 0 {
                 texture_data.push((
                     (Crc::Key(hash_string("*".as_bytes(), Some(tex.info().asset_key.key()))), tex.info().asset_type),
@@ -390,11 +426,11 @@ impl Level {
             animation_vals.iter().filter(|(_, k)| k & gamemodemask != 0).flat_map(|(x, _)| x).cloned().collect::<Vec<_>>()
         }).collect::<Vec<_>>();
         
+        let animations_blocks = animations_blocks.into_par_iter().map(|data| (data.len(), CompressedBlock { data }.dump())).collect::<Vec<_>>();
+        
         let mut animation_block_infos = self.animation_block_infos.clone();
-        for (info, data) in zip(&mut animation_block_infos, animations_blocks) {
+        for (info, (size, data)) in zip(&mut animation_block_infos, animations_blocks) {
             pak_data.extend(vec![0u8; ((pak_data.len() + 4095) & 0xfffff000)-pak_data.len()]);
-            let size = data.len();
-            let data = CompressedBlock { data }.dump();
             info.offset = pak_data.len() as u32;
             info.size = size as u32;
             info.size_comp = data.len() as u32;
@@ -402,8 +438,9 @@ impl Level {
         }
         info!("animations in {:?}", time.elapsed());
 
-        let effects = self.effects.iter().sorted_by(|a, b| a.0.key().cmp(&b.0.key())).map(|(key, effect)| {
-            let vals = effect.dump::<O>();
+        let effect_vals = self.effects.iter().sorted_by(|a, b| a.0.key().cmp(&b.0.key())).collect::<Vec<_>>()
+            .into_par_iter().map(|(key, effect)| (key, effect, effect.dump::<O>())).collect::<Vec<_>>();
+        let effects = effect_vals.into_iter().map(|(key, effect, vals)| {
             let effect = pak::EffectInfo { key: key.clone(), gamemodemask: effect.gamemodemask, offset: block1.len() as u32, size: vals.len() as u32 };
             block1.extend(vals);
             effect
@@ -437,21 +474,21 @@ impl Level {
         collision_road.sort_unstable_by_key(|x| 
             x.str().and_then(|x| x.split('_').last().and_then(|x| x.parse::<usize>().ok())).unwrap_or_default()
         );
-        for key in normal.into_iter().chain(collision_road) {
+        // the vertex data of every mesh is dumped up front, it sets the buffer offsets used by the mesh infos
+        let dump_meshes = |keys: Vec<&Crc>| keys.into_par_iter().map(|key| {
             let mut mesh = self.meshes.get(key).unwrap().clone();
-            if let Some(val) = dump_vertex_data::<O>(&mut mesh) {
-                mesh_data.push(val);
-            }
+            let val = dump_vertex_data::<O>(&mut mesh);
+            (mesh, val)
+        }).collect::<Vec<_>>();
+        for (mesh, val) in dump_meshes(normal.into_iter().chain(collision_road).collect()) {
+            mesh_data.extend(val);
             block1.extend(mesh.dump::<O>(block1.len(), &mut infos));
             block1.extend(vec![0u8; ((block1.len() + 15) & 0xFFFFFFF0) - block1.len()]);
         }
         let terrain_start_offset = block1.len() as u32;
         block1.extend(vec![0xFFu8; 16]);
-        for key in terrain {
-            let mut mesh = self.meshes.get(key).unwrap().clone();
-            if let Some(val) = dump_vertex_data::<O>(&mut mesh) {
-                mesh_data.push(val);
-            }
+        for (mesh, val) in dump_meshes(terrain) {
+            mesh_data.extend(val);
             block1.extend(mesh.dump_terrain::<O>(block1.len(), terrain_start_offset, &mut infos));
         }
         block1.extend(vec![0u8; ((block1.len() + 15) & 0xFFFFFFF0) - block1.len()]);
@@ -653,17 +690,18 @@ impl Level {
         info!("block2 in {:?}", time.elapsed());
 
         // rest of pak
+        let (size1, size2) = (block1.len(), block2.len());
+        let (data1, data2) = rayon::join(|| CompressedBlock { data: block1 }.dump(), || CompressedBlock { data: block2 }.dump());
+
         pak_data.extend(vec![0u8; ((pak_data.len() + 4095) & 0xfffff000)-pak_data.len()]);
-        let size = block1.len();
-        let data = CompressedBlock { data: block1 }.dump();
+        let (size, data) = (size1, data1);
         pak_header.block1_size = size as u32;
         pak_header.block1_size_comp = data.len() as u32;
         pak_header.block1_offset = pak_data.len() as u32;
         pak_data.extend(data);
 
         pak_data.extend(vec![0u8; ((pak_data.len() + 4095) & 0xfffff000)-pak_data.len()]);
-        let size = block2.len();
-        let data = CompressedBlock { data: block2 }.dump();
+        let (size, data) = (size2, data2);
         pak_header.block2_size = size as u32;
         pak_header.block2_size_comp = data.len() as u32;
         pak_header.block2_offset = pak_data.len() as u32;
@@ -689,46 +727,27 @@ impl Level {
         bin_header.version = if TypeId::of::<O>() == TypeId::of::<LE>() { 2 } else { 1 };
 
         bin_data.extend(vec![0u8; ((bin_data.len() + 2047) & 0xfffff800)-bin_data.len()]);
-        let mut mesh_asset_handles = mesh_data.into_iter().map(|((key, kind), data)| {
-            let size = data.len() as u32;
-            let offset = bin_data.len() as u32;
-            let size_comp = if size != 0 {
-                let data = CompressedBlock { data }.dump();
-                let size_comp = data.len() as u32;
-                bin_data.extend(data);
-                bin_data.extend(vec![0u8; ((bin_data.len() + 2047) & 0xfffff800)-bin_data.len()]);
-                size_comp
-            } else { 0 };
-            bin::AssetHandle { key, offset, size, size_comp, kind }
-        }).collect::<Vec<_>>();
-
-        let mut texture_asset_handles = texture_data.into_iter().map(|((key, kind), data)| {
-            let size = data.len() as u32;
-            let offset = bin_data.len() as u32;
-            let size_comp = if size != 0 {
-                let data = CompressedBlock { data }.dump();
-                let size_comp = data.len() as u32;
-                bin_data.extend(data);
-                bin_data.extend(vec![0u8; ((bin_data.len() + 2047) & 0xfffff800)-bin_data.len()]);
-                size_comp
-            } else { 0 };
-            bin::AssetHandle { key, offset, size, size_comp, kind }
-        }).collect::<Vec<_>>();
-
-        mesh_asset_handles.extend(self.radiosity.iter().map(|(key, data)| {
-            let kind = data.usage;
-            let data = data.data.dump_bytes::<O>();
-            let size = data.len() as u32;
-            let offset = bin_data.len() as u32;
-            let size_comp = if size != 0 {
-                let data = CompressedBlock { data }.dump();
-                let size_comp = data.len() as u32;
-                bin_data.extend(data);
-                bin_data.extend(vec![0u8; ((bin_data.len() + 2047) & 0xfffff800)-bin_data.len()]);
-                size_comp
-            } else { 0 };
-            bin::AssetHandle { key: key.clone(), offset, size, size_comp, kind }
-        }));
+        // assets are compressed in parallel, then laid out in order
+        fn pack_assets(bin_data: &mut Vec<u8>, assets: Vec<((Crc, u32), Vec<u8>)>) -> Vec<bin::AssetHandle> {
+            let assets = assets.into_par_iter().map(|(key, data)| {
+                let size = data.len() as u32;
+                (key, size, (size != 0).then(|| CompressedBlock { data }.dump()))
+            }).collect::<Vec<_>>();
+            assets.into_iter().map(|((key, kind), size, data)| {
+                let offset = bin_data.len() as u32;
+                let size_comp = if let Some(data) = data {
+                    let size_comp = data.len() as u32;
+                    bin_data.extend(data);
+                    bin_data.extend(vec![0u8; ((bin_data.len() + 2047) & 0xfffff800)-bin_data.len()]);
+                    size_comp
+                } else { 0 };
+                bin::AssetHandle { key, offset, size, size_comp, kind }
+            }).collect()
+        }
+        let mut mesh_asset_handles = pack_assets(&mut bin_data, mesh_data);
+        let mut texture_asset_handles = pack_assets(&mut bin_data, texture_data);
+        let radiosity_data = self.radiosity.iter().map(|(key, data)| ((key.clone(), data.usage), data.data.dump_bytes::<O>())).collect();
+        mesh_asset_handles.extend(pack_assets(&mut bin_data, radiosity_data));
 
         mesh_asset_handles.sort_by_key(|x| x.key.key());
         texture_asset_handles.sort_by_key(|x| x.key.key());
@@ -765,30 +784,35 @@ impl Level {
         info!("storing level");
 
         // std::fs::create_dir_all(path.join("assets").join("raw")).ok();
+
+        // masks are written with the names of this level's gamemodes, the names go in the dump so it can be read back on its own
+        if !self.gamemodes.is_empty() {
+            writer.join("gamemodes").write_val(&self.gamemodes);
+        }
     
-        writer.join("bin_header.json").write(&to_vec_pretty(&self.bin_header).unwrap());
+        writer.join("bin_header").write_val(&self.bin_header);
         self.bin_strings.to_file(writer.join("bin_strings"));
 
-        writer.join("pak_header.json").write(&to_vec_pretty(&self.pak_header).unwrap());
+        writer.join("pak_header").write_val(&self.pak_header);
         self.pak_strings.to_file(writer.join("pak_strings"));
         info!("headers in {:?}", time.elapsed());
 
-        writer.join("objas.json").write(&to_vec_pretty(&self.objas).unwrap());
-        writer.join("obj0s.json").write(&to_vec_pretty(&self.obj0s).unwrap());
-        writer.join("pak_vals_a.json").write(&to_vec_pretty(&self.pak_vals_a).unwrap());
+        writer.join("objas").write_val(&self.objas);
+        writer.join("obj0s").write_val(&self.obj0s);
+        writer.join("pak_vals_a").write_masked(&self.pak_vals_a, &self.gamemodes);
         info!("unused objs in {:?}", time.elapsed());
 
-        for (key, data) in &self.meshes {
-            writer.join("meshes").join(key.to_string()).with_extension("json").write(&to_vec_pretty(&data).unwrap());
-        }
+        let skeletons = self.meshes.par_iter().filter_map(|(key, data)| {
+            data.to_file(&writer, key, &self.gamemodes).map(|skeleton| (key.clone(), skeleton))
+        }).collect::<HashMap<_, _>>();
         info!("meshes in {:?}", time.elapsed());
-        for (key, data) in &self.effects {
-            data.to_file(writer.join("effects").join(key.to_string()));
-        }
+        self.effects.par_iter().for_each(|(key, data)| {
+            data.to_file(writer.join("effects").join(key.to_string()), &self.gamemodes);
+        });
         info!("effects in {:?}", time.elapsed());
         for (key, data) in &self.foliages {
             let (info, data): (Vec<_>, Vec<_>) = Iterator::unzip(data.iter().map(|(a,b)| (a,b)));
-            writer.join("foliage").join(key.to_string()).with_extension("json").write(&to_vec_pretty(&info).unwrap());
+            writer.join("foliage").join(key.to_string()).write_val(&info);
             for (i, data) in data.iter().enumerate() {
                 writer.join("foliage").join(format!("{}-{}", key.to_string(), i)).with_extension("bin").write(&data.dump_bytes::<LE>());
             }
@@ -803,29 +827,39 @@ impl Level {
         }
         info!("gfxs in {:?}", time.elapsed());
 
-        writer.join("animation_block_infos.json").write(&to_vec_pretty(&self.animation_block_infos).unwrap());
-        for (key, data) in &self.animations {
-            writer.join("animations").join(key.to_string()).with_extension("json").write(&to_vec_pretty(&data).unwrap());
-        }
+        writer.join("animation_block_infos").write_val(&self.animation_block_infos);
+        let animation_skeletons = self.animations.par_iter().filter_map(|(key, data)| {
+            writer.join("animations").join(key.to_string()).write_masked(&data, &self.gamemodes);
+            // any skeleton would match an animation without bones
+            if data.keys.is_empty() {
+                return None;
+            }
+            skeletons.iter().sorted_by_key(|(k, _)| *k).find(|(_, skeleton)| 
+                data.keys.iter().all(|bone| skeleton.bone_index(bone).is_some())
+            ).map(|(skeleton, _)| (key.to_string(), skeleton.to_string()))
+        }).collect::<HashMap<_, _>>();
+        writer.join("animation_skeletons").write_val(&animation_skeletons);
         info!("animations in {:?}", time.elapsed());
 
-        for (key, tex) in &self.textures {
-            tex.to_file(writer.join("textures").join(key.to_string()));
-        }
+        self.textures.par_iter().for_each(|(key, tex)| {
+            tex.to_file(writer.join("textures").join(key.to_string()), &self.gamemodes);
+        });
         info!("textures in {:?}", time.elapsed());
 
-        for (key, data) in &self.radiosity {
-            writer.join("radiosity").join(key.to_string()).with_extension("json").write(&to_vec_pretty(&data).unwrap());
-        }
+        self.radiosity.par_iter().for_each(|(key, data)| {
+            writer.join("radiosity").join(key.to_string()).write_val(&data);
+        });
         info!("radiosity in {:?}", time.elapsed());
 
-        writer.join("pfield_infos.json").write(&to_vec_pretty(&self.pfield_infos).unwrap());
+        writer.join("pfield_infos").write_val(&self.pfield_infos);
 
         info!("packed items in {:?}", time.elapsed());
 
+        // like the gamemodes, the languages go in the dump so added ones are read back as strings
+        writer.join("languages").write_val(&self.sub_blocks1.languages().into_iter().chain(self.sub_blocks2.languages()).collect::<Vec<_>>());
         self.string_keys.to_file(writer.join("string_keys"));
-        self.sub_blocks1.to_file(writer.join("sub_blocks1"), &self.string_keys);
-        self.sub_blocks2.to_file(writer.join("sub_blocks2"), &self.string_keys);
+        self.sub_blocks1.to_file(writer.join("sub_blocks1"), &self.string_keys, &self.gamemodes);
+        self.sub_blocks2.to_file(writer.join("sub_blocks2"), &self.string_keys, &self.gamemodes);
         info!("sub blocks in {:?}", time.elapsed());
 
         if *types::ANIM_TABLES.lock().unwrap() {
@@ -845,50 +879,56 @@ impl Level {
             let script_manager = Arc::new(script_manager);
             for anim in anim_scripts {
                 let val = lua_stuff::load_anim(script_manager.clone(), anim.clone());
-                writer.join("animation_tables").join(anim).with_extension("json").write(&to_vec_pretty(&val).unwrap());
+                writer.join("animation_tables").join(anim).write_val(&val);
             }
             info!("animation tables in {:?}", time.elapsed());
         }
     }
 
-    pub fn from_file(reader: Reader) -> Self {
+    /// Reads a dumped level, if the dump doesn't name its gamemodes or languages they are taken from the `level_info` if one is given
+    pub fn from_file(reader: Reader, level_info: Option<&LevelInfo>) -> Self {
         let time: Instant = Instant::now();
         info!("reading level");        
         
         let lua: lua_stuff::LuaCompiler = lua_stuff::LuaCompiler::new().unwrap();
 
-        let bin_header = serde_json::from_slice::<bin::Header>(&reader.join("bin_header.json").read()).unwrap();
+        let bin_header = reader.join("bin_header").read_val::<bin::Header>();
         let bin_strings = types::Strings::from_file(reader.join("bin_strings"));
 
-        let pak_header = serde_json::from_slice::<pak::Header>(&reader.join("pak_header.json").read()).unwrap();
+        let pak_header = reader.join("pak_header").read_val::<pak::Header>();
         let pak_strings = types::Strings::from_file(reader.join("pak_strings"));
         info!("headers in {:?}", time.elapsed());
 
-        let objas = serde_json::from_slice::<Vec<pak::ObjA>>(&reader.join("objas.json").read()).unwrap();
-        let obj0s = serde_json::from_slice::<Vec<pak::Obj0>>(&reader.join("obj0s.json").read()).unwrap();
-        let pak_vals_a = serde_json::from_slice::<Vec<pak::BlockAVal>>(&reader.join("pak_vals_a.json").read()).unwrap();
+        let animation_block_infos = reader.join("animation_block_infos").read_val::<Vec<pak::AnimationBlockInfo>>();
+        let gamemodes = if reader.join("gamemodes").find_val().is_some() {
+            reader.join("gamemodes").read_val::<Vec<Crc>>()
+        } else {
+            level_info.map(|x| x.level_gamemodes(reader.name(), animation_block_infos.len())).unwrap_or_default()
+        };
+
+        let objas = reader.join("objas").read_val::<Vec<pak::ObjA>>();
+        let obj0s = reader.join("obj0s").read_val::<Vec<pak::Obj0>>();
+        let pak_vals_a = reader.join("pak_vals_a").read_masked::<Vec<pak::BlockAVal>>(&gamemodes);
         info!("unused objs in {:?}", time.elapsed());
 
-        let mut meshes = HashMap::new();
-        for path in reader.join("meshes") {
+        let meshes = reader.join("meshes").into_iter().collect::<Vec<_>>().into_par_iter().map(|path| {
             let key = Crc::from_string(path.name());
-            let data = serde_json::from_slice::<Mesh>(&path.read()).unwrap();
-            meshes.insert(key, data);
-        }
+            let data = path.read_masked::<Mesh>(&gamemodes);
+            (key, data)
+        }).collect::<HashMap<_, _>>();
         info!("meshes in {:?}", time.elapsed());
 
-        let mut effects = HashMap::new();
-        for path in reader.join("effects") {
+        let effects = reader.join("effects").into_iter().collect::<Vec<_>>().into_par_iter().map(|path| {
             let key = Crc::from_string(path.name());
-            let data = GameObjs::from_file(path);
-            effects.insert(key, data);
-        }
+            let data = GameObjs::from_file(path, &gamemodes);
+            (key, data)
+        }).collect::<HashMap<_, _>>();
         info!("effects in {:?}", time.elapsed());
 
         let mut foliages = HashMap::new();
-        for path in reader.join("foliage").into_iter().filter(|x| x.path().extension().unwrap_or(OsStr::new("")).to_str() == Some("json")) {
+        for path in reader.join("foliage").into_iter().filter(|x| x.format().is_some()) {
             let key = Crc::from_string(path.name());
-            let info = serde_json::from_slice::<Vec<pak::FoliageInfo>>(&path.read()).unwrap();
+            let info = path.read_val::<Vec<pak::FoliageInfo>>();
             let mut data = Vec::with_capacity(info.len());
             for i in 0..info.len() {
                 let dat = path.with_file_name(&format!("{}-{}.bin", key.to_string(), i)).read();
@@ -915,38 +955,41 @@ impl Level {
         }
         info!("gfxs in {:?}", time.elapsed());
 
-        let animation_block_infos = serde_json::from_slice::<Vec<pak::AnimationBlockInfo>>(&reader.join("animation_block_infos.json").read()).unwrap();
-        let mut animations = HashMap::new();
-        for path in reader.join("animations") {
+        let animations = reader.join("animations").into_iter().collect::<Vec<_>>().into_par_iter().map(|path| {
             let key = Crc::from_string(path.name());
-            let data = serde_json::from_slice::<Animation>(&path.read()).unwrap();
-            animations.insert(key, data);
-        }
+            let data = path.read_masked::<Animation>(&gamemodes);
+            (key, data)
+        }).collect::<HashMap<_, _>>();
         info!("animations in {:?}", time.elapsed());
 
-        let mut textures = HashMap::new();
-        for path in reader.join("textures").into_iter().filter(|x| x.path().extension().unwrap_or(OsStr::new("")).to_str() == Some("json")) {
-            let key = Crc::from_string(path.name());
-            let data = bin::Tex::from_file(path);
-            textures.insert(key, data);
-        }
+        let textures = reader.join("textures").into_iter().filter(|x| x.format().is_some())
+            .collect::<Vec<_>>().into_par_iter().map(|path| {
+                let key = Crc::from_string(path.name());
+                let data = bin::Tex::from_file(path, &gamemodes);
+                (key, data)
+            }).collect::<HashMap<_, _>>();
         info!("textures in {:?}", time.elapsed());
 
         let mut radiosity = HashMap::new();
         for path in reader.join("radiosity") {
             let key = Crc::from_string(path.name());
-            let data = serde_json::from_slice::<bin::Radiosity>(&path.read()).unwrap();
+            let data = path.read_val::<bin::Radiosity>();
             radiosity.insert(key, data);
         }
         info!("radiosity in {:?}", time.elapsed());
 
-        let pfield_infos = serde_json::from_slice::<Vec<pak::PFieldInfo>>(&reader.join("pfield_infos.json").read()).unwrap();
+        let pfield_infos = reader.join("pfield_infos").read_val::<Vec<pak::PFieldInfo>>();
 
         info!("packed items in {:?}", time.elapsed());
 
+        let languages = if reader.join("languages").find_val().is_some() {
+            reader.join("languages").read_val::<Vec<Crc>>()
+        } else {
+            level_info.map(LevelInfo::languages).unwrap_or_default()
+        };
         let string_keys = types::StringKeys::from_file(reader.join("string_keys"));
-        let sub_blocks1 = types::SubBlocks::from_file(reader.join("sub_blocks1"), &lua);
-        let sub_blocks2 = types::SubBlocks::from_file(reader.join("sub_blocks2"), &lua);
+        let sub_blocks1 = types::SubBlocks::from_file(reader.join("sub_blocks1"), &string_keys, &lua, &gamemodes, &languages);
+        let sub_blocks2 = types::SubBlocks::from_file(reader.join("sub_blocks2"), &string_keys, &lua, &gamemodes, &languages);
         info!("sub blocks in {:?}", time.elapsed());
 
         let vertex_formats = HashMap::new();
@@ -967,6 +1010,7 @@ impl Level {
             effects,
             pfield_infos,
             animation_block_infos,
+            gamemodes,
             string_keys,
             sub_blocks1,
             sub_blocks2,
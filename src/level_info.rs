@@ -1,13 +1,15 @@
 
 use std::{fs, path::Path};
 use zerocopy::{ByteOrder, LE, BE};
+use itertools::Itertools;
 use log::warn;
 use serde::{Serialize, Deserialize};
 use lotrc_rs_proc::OrderedData;
 
 use super::{
+    locale,
     lua_stuff,
-    types::{self, Crc, OrderedData, OrderedDataVec},
+    types::{self, Crc, LangStrings, OrderedData, OrderedDataVec},
     read_write::{Reader, Writer, PathStuff},
 };
 
@@ -36,6 +38,22 @@ impl From<[u8; 32]> for Name {
     }
 }
 
+impl Name {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<&str> for Name {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        if value.len() >= 32 {
+            return Err(format!("Name {} is longer than 31 bytes", value));
+        }
+        Ok(Name(value.into()))
+    }
+}
+
 impl OrderedData for Name {
     type LE = [u8; 32];
     type BE = [u8; 32];
@@ -64,6 +82,7 @@ pub struct LevelVal {
     pub key_name: Crc,
     pub key_description: Crc,
     pub dlc: u32,
+    #[serde(with = "types::gamemode_mask")]
     pub gamemodes: u32,
 }
 #[derive(Debug, Default, Clone, OrderedData, Serialize, Deserialize)]
@@ -77,17 +96,18 @@ use serde_with::serde_as;
 #[serde_as]
 #[derive(Default, Debug, Serialize, Deserialize)]
 pub struct LevelInfo {
-    header: Header,
+    pub header: Header,
     #[serde(skip)]
-    strings: types::Strings,
+    pub strings: types::Strings,
     #[serde(skip)]
-    string_keys: types::StringKeys,
+    pub string_keys: types::StringKeys,
     #[serde(skip)]
-    locale_strings: types::SubBlocks,
-    levels: Vec<LevelVal>,
-    gamemodes: Vec<GamemodeVal>,
+    pub locale_strings: types::SubBlocks,
+    pub levels: Vec<LevelVal>,
+    pub gamemodes: Vec<GamemodeVal>,
+    /// The bytes between the header and the gamemodes (0x38..0x13c), their layout isn't known so they're kept as is
     #[serde_as(as = "serde_with::hex::Hex")]
-    extra: Vec<u8>
+    pub extra: Vec<u8>
 }
 
 impl LevelInfo {
@@ -114,7 +134,10 @@ impl LevelInfo {
         let strings = types::Strings::from_data::<O>(data, header.strings_offset as usize, header.strings_num as usize);
         types::update_strings(&strings.strings);
         let string_keys = types::StringKeys::from_data::<O>(data, header.string_keys_offset as usize);
-        let locale_strings = types::SubBlocks::from_data::<O>(data, header.locale_strings_offset as usize, &lua);
+        let mut locale_strings = types::SubBlocks::from_data::<O>(data, header.locale_strings_offset as usize, &lua);
+        // every block of the level_info is a language
+        let languages = locale_strings.block_headers.iter().map(|x| x.key.clone()).collect::<Vec<_>>();
+        locale_strings.read_languages::<O>(&string_keys, &languages);
         let gamemodes = OrderedDataVec::from_bytes::<O>(&data[header.gamemodes_offset as usize..], header.gamemodes_num as usize);
         let levels = OrderedDataVec::from_bytes::<O>(&data[header.levels_offset as usize..], header.levels_num as usize);
         let extra = data[0x38..0x13c].to_vec();
@@ -156,24 +179,114 @@ impl LevelInfo {
         ].copy_from_slice(self.locale_strings.dump::<O>(&lua).as_slice());
         self.gamemodes.to_bytes::<O>(&mut data[dump_header.gamemodes_offset as usize..]);
         self.levels.to_bytes::<O>(&mut data[dump_header.levels_offset as usize..]);
+        assert!(self.extra.len() == 0x13c - 0x38, "level_info extra should have {} bytes", 0x13c - 0x38);
         data[0x38..0x13c].copy_from_slice(&self.extra[..]);
         data
     }
 
+    /// Adds a localized string for a new level or gamemode, the key name is added to the debug strings so it resolves when parsed again
+    fn add_string(&mut self, key: &str, text: &str) -> Result<Crc, String> {
+        let key = Crc::from_string(key);
+        if !self.string_keys.vals.iter().any(|x| x.key == key) {
+            let english = Crc::Key(LangStrings::KEY_ENGLISH);
+            locale::add_key(&mut self.string_keys, &mut [&mut self.locale_strings], key.clone(), &[(english.clone(), text.to_string())], &english)?;
+        }
+        if let Some(name) = key.str() {
+            if !self.strings.strings.iter().any(|x| x == name) {
+                self.strings.strings.push(name.to_string());
+            }
+        }
+        Ok(key)
+    }
+
+    /// Mask of gamemodes given by key
+    pub fn gamemode_mask(&self, gamemodes: &[Crc]) -> Result<u32, String> {
+        gamemodes.iter().map(|x| self.gamemodes.iter().position(|g| g.key == *x)
+            .map(|i| 1u32 << i).ok_or(format!("Unknown gamemode {}", x.to_string()))
+        ).fold_ok(0, |a, b| a | b)
+    }
+
+    /// Registers a new level, its name and description are added as strings under `<name>_name` and `<name>_desc`
+    pub fn add_level(&mut self, name: &str, gamemodes: &[Crc], dlc: u32, title: &str, description: &str) -> Result<(), String> {
+        if self.levels.iter().any(|x| &*x.name.0 == name) {
+            return Err(format!("Level {} already exists", name));
+        }
+        let val = LevelVal {
+            name: name.try_into()?,
+            gamemodes: self.gamemode_mask(gamemodes)?,
+            key_name: self.add_string(&format!("{}_name", name), title)?,
+            key_description: self.add_string(&format!("{}_desc", name), description)?,
+            dlc,
+        };
+        self.levels.push(val);
+        Ok(())
+    }
+
+    /// Registers a new gamemode, levels have to be given it with add_level or by editing their gamemode masks
+    pub fn add_gamemode(&mut self, key: &str, title: &str, description: &str) -> Result<(), String> {
+        let key_crc = Crc::from_string(key);
+        if self.gamemodes.iter().any(|x| x.key == key_crc) {
+            return Err(format!("Gamemode {} already exists", key));
+        }
+        if self.gamemodes.len() >= 32 {
+            return Err("Gamemode masks only have room for 32 gamemodes".to_string());
+        }
+        if !self.strings.strings.iter().any(|x| x == key) {
+            self.strings.strings.push(key.to_string());
+        }
+        let val = GamemodeVal {
+            key: key_crc,
+            key_name: self.add_string(&format!("{}_name", key), title)?,
+            key_description: self.add_string(&format!("{}_desc", key), description)?,
+        };
+        self.gamemodes.push(val);
+        Ok(())
+    }
+
+    /// Gamemode keys, in mask bit order
+    pub fn gamemode_names(&self) -> Vec<Crc> {
+        self.gamemodes.iter().map(|x| x.key.clone()).collect()
+    }
+
+    /// Names of the language blocks
+    pub fn languages(&self) -> Vec<Crc> {
+        self.locale_strings.block_headers.iter().map(|x| x.key.clone()).collect()
+    }
+
+    /// Gamemode keys of the level with the given name, ignoring case. The masks in a level only have bits for its own gamemodes,
+    /// bit i of them is the i-th gamemode set in its mask here. Empty if the level isn't listed or doesn't have `num` gamemodes
+    pub fn level_gamemodes(&self, name: &str, num: usize) -> Vec<Crc> {
+        let Some(level) = self.levels.iter().find(|x| x.name.as_str().eq_ignore_ascii_case(name)) else {
+            return vec![];
+        };
+        let names = (0..32).filter(|i| level.gamemodes & (1 << i) != 0).filter_map(|i| self.gamemodes.get(i).map(|x| x.key.clone())).collect::<Vec<_>>();
+        if names.len() != num {
+            warn!("The level_info gives {} {} gamemodes but it has {}, masks are left as numbers", name, names.len(), num);
+            return vec![];
+        }
+        names
+    }
+
     pub fn to_file(&self, writer: Writer) {
-        writer.join("index.json").write(&serde_json::to_vec_pretty(self).unwrap());
+        writer.join("index").write_masked(self, &self.gamemode_names());
         self.strings.to_file(writer.join("debug_strings"));
         self.string_keys.to_file(writer.join("string_keys"));
-        self.locale_strings.to_file(writer.join("locale_strings"), &self.string_keys);
+        self.locale_strings.to_file(writer.join("locale_strings"), &self.string_keys, &[]);
     }
 
     pub fn from_file(reader: Reader) -> Self {
         let lua = lua_stuff::LuaCompiler::new().unwrap();
 
-        let mut val = serde_json::from_slice::<Self>(&reader.join("index.json").read()).unwrap();
+        // the level masks are read with the gamemode names of this level_info, so those are read first
+        let index = reader.join("index").read_val::<serde_json::Value>();
+        let gamemodes = serde_json::from_value::<Vec<GamemodeVal>>(index["gamemodes"].clone()).unwrap();
+        let names = gamemodes.iter().map(|x| x.key.clone()).collect::<Vec<_>>();
+        let mut val = types::with_gamemodes(&names, || serde_json::from_value::<Self>(index)).unwrap_or_else(|e| panic!("Could not read {:?}: {}", reader, e));
         val.strings = types::Strings::from_file(reader.join("debug_strings"));
         val.string_keys = types::StringKeys::from_file(reader.join("string_keys"));
-        val.locale_strings = types::SubBlocks::from_file(reader.join("locale_strings"), &lua);
+        // every block of the level_info is a language
+        let languages = reader.join("locale_strings").join("index").read_val::<types::SubBlocks>().block_headers.into_iter().map(|x| x.key).collect::<Vec<_>>();
+        val.locale_strings = types::SubBlocks::from_file(reader.join("locale_strings"), &val.string_keys, &lua, &[], &languages);
         val
     }
 }
\ No newline at end of file
@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+use log::warn;
+
+use super::types::{Crc, LangStrings, StringKeys, Strings, SubBlock, SubBlocks, SubBlocksBlockHeader};
+
+/// Every localized string, one row per string key and one column per language
+#[derive(Debug, Default, Clone)]
+pub struct Table {
+    pub languages: Vec<Crc>,
+    pub rows: Vec<(Crc, Vec<String>)>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Report {
+    /// Keys of the level that the table has no string for, these keep their old strings
+    pub missing: Vec<Crc>,
+    /// Keys of the table that the level doesn't have
+    pub extra: Vec<Crc>,
+    /// Languages of the table that the level doesn't have
+    pub languages: Vec<Crc>,
+}
+
+fn lang_blocks<'a>(blocks: &'a [&SubBlocks]) -> Vec<(&'a Crc, &'a LangStrings)> {
+    blocks.iter().flat_map(|x| x.block_headers.iter().zip(&x.blocks)).filter_map(|(h, b)| match b {
+        SubBlock::LangStrings(val) => Some((&h.key, val)),
+        _ => None
+    }).collect()
+}
+
+pub fn export(keys: &StringKeys, blocks: &[&SubBlocks]) -> Table {
+    let langs = lang_blocks(blocks);
+    Table {
+        languages: langs.iter().map(|(k, _)| (*k).clone()).collect(),
+        rows: keys.vals.iter().enumerate().map(|(i, key)|
+            (key.key.clone(), langs.iter().map(|(_, x)| x.strings.get(i).cloned().unwrap_or_default()).collect())
+        ).collect(),
+    }
+}
+
+/// Sets the strings of the table by key. Keys the level doesn't have are only added if `add` is set,
+/// languages without a string for them get the English one
+pub fn import(table: &Table, keys: &mut StringKeys, blocks: &mut [&mut SubBlocks], add: bool) -> Report {
+    let mut report = Report::default();
+    let rows = table.rows.iter().map(|(k, v)| (k.key(), v)).collect::<HashMap<_, _>>();
+    let english = table.languages.iter().position(|x| x.key() == LangStrings::KEY_ENGLISH);
+    let mut new_keys = keys.vals.iter().map(|x| x.key.clone()).collect::<Vec<_>>();
+    let extra = table.rows.iter().filter(|(k, _)| !new_keys.contains(k)).map(|(k, _)| k.clone()).collect::<Vec<_>>();
+    report.missing = new_keys.iter().filter(|k| rows.get(&k.key()).is_none_or(|x| x.iter().all(|x| x.is_empty()))).cloned().collect();
+    report.languages = table.languages.iter().filter(|lang| !blocks.iter().any(|x| x.block_headers.iter().zip(&x.blocks)
+        .any(|(h, b)| h.key == **lang && matches!(b, SubBlock::LangStrings(_))))).cloned().collect();
+
+    for sub_blocks in blocks.iter_mut() {
+        for (header, block) in sub_blocks.block_headers.iter().zip(sub_blocks.blocks.iter_mut()) {
+            let SubBlock::LangStrings(block) = block else { continue };
+            let column = table.languages.iter().position(|x| *x == header.key);
+            for (key, string) in new_keys.iter().zip(block.strings.iter_mut()) {
+                if let Some(val) = column.and_then(|i| rows.get(&key.key()).map(|x| &x[i])).filter(|x| !x.is_empty()) {
+                    *string = val.clone();
+                }
+            }
+            if add {
+                block.strings.extend(extra.iter().map(|key| {
+                    let row = rows[&key.key()];
+                    column.map(|i| &row[i]).filter(|x| !x.is_empty()).or(english.map(|i| &row[i])).cloned().unwrap_or_default()
+                }));
+            }
+        }
+    }
+    if add {
+        new_keys.extend(extra.iter().cloned());
+        *keys = StringKeys::new(new_keys);
+    }
+    report.extra = extra;
+    report
+}
+
+fn csv_field(val: &str) -> String {
+    if val.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", val.replace('"', "\"\""))
+    } else {
+        val.to_string()
+    }
+}
+
+pub fn to_csv(table: &Table) -> String {
+    let mut out = String::new();
+    let header = ["key".to_string()].into_iter().chain(table.languages.iter().map(|x| x.to_string())).map(|x| csv_field(&x)).collect::<Vec<_>>();
+    out.push_str(&header.join(","));
+    out.push_str("\r\n");
+    for (key, vals) in &table.rows {
+        let row = [key.to_string()].iter().chain(vals).map(|x| csv_field(x)).collect::<Vec<_>>();
+        out.push_str(&row.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+fn parse_csv(data: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut field = String::new();
+    let mut chars = data.trim_start_matches('\u{feff}').chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => { chars.next(); field.push('"'); },
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            c if quoted => field.push(c),
+            ',' => row.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => (),
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            },
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("Unterminated quoted field".to_string());
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+pub fn from_csv(data: &str) -> Result<Table, String> {
+    let mut rows = parse_csv(data)?.into_iter();
+    let header = rows.next().ok_or("Empty csv")?;
+    let languages = header.iter().skip(1).map(|x| Crc::from_string(x)).collect::<Vec<_>>();
+    let rows = rows.enumerate().filter(|(_, x)| !(x.len() == 1 && x[0].is_empty())).map(|(i, mut row)| {
+        if row.len() != header.len() {
+            return Err(format!("Row {} has {} fields, expected {}", i + 2, row.len(), header.len()));
+        }
+        let key = Crc::from_string(&row.remove(0));
+        Ok((key, row))
+    }).collect::<Result<Vec<_>, _>>()?;
+    Ok(Table { languages, rows })
+}
+
+fn po_string(val: &str) -> String {
+    let val = val.replace('\\', "\\\\").replace('"', "\\\"").replace('\t', "\\t").replace('\r', "\\r");
+    let lines = val.split_inclusive('\n').collect::<Vec<_>>();
+    if lines.len() > 1 {
+        format!("\"\"\n{}", lines.iter().map(|x| format!("\"{}\"", x.replace('\n', "\\n"))).collect::<Vec<_>>().join("\n"))
+    } else {
+        format!("\"{}\"", val.replace('\n', "\\n"))
+    }
+}
+
+fn po_unescape(val: &str) -> String {
+    let mut out = String::new();
+    let mut chars = val.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some(c) => out.push(c),
+            None => (),
+        }
+    }
+    out
+}
+
+/// Gettext catalog for one language, the English strings are the source text and the keys the context
+pub fn to_po(table: &Table, language: &Crc) -> Result<String, String> {
+    let column = table.languages.iter().position(|x| x == language).ok_or(format!("No language {}", language.to_string()))?;
+    let english = table.languages.iter().position(|x| x.key() == LangStrings::KEY_ENGLISH);
+    let mut out = format!("msgid \"\"\nmsgstr \"\"\n\"Language: {}\\n\"\n\"Content-Type: text/plain; charset=UTF-8\\n\"\n", language.to_string());
+    for (key, vals) in &table.rows {
+        let source = english.map(|i| &vals[i]).filter(|x| !x.is_empty()).cloned().unwrap_or_else(|| key.to_string());
+        let target = if Some(column) == english { "" } else { &vals[column] };
+        out.push_str(&format!("\nmsgctxt {}\nmsgid {}\nmsgstr {}\n", po_string(&key.to_string()), po_string(&source), po_string(target)));
+    }
+    Ok(out)
+}
+
+/// Reads a catalog written by to_po, untranslated entries are left out. English catalogs take their strings from msgid
+pub fn from_po(data: &str) -> Result<Table, String> {
+    let mut entries = vec![];
+    let mut entry: HashMap<&str, String> = HashMap::new();
+    let mut current = None;
+    for (i, line) in data.lines().map(|x| x.trim()).enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            if !entry.is_empty() {
+                entries.push(std::mem::take(&mut entry));
+            }
+            current = None;
+            continue;
+        }
+        let (name, val) = match line.split_once(' ') {
+            Some((name, val)) if ["msgctxt", "msgid", "msgstr"].contains(&name) => (Some(name), val),
+            _ => (None, line),
+        };
+        let val = val.strip_prefix('"').and_then(|x| x.strip_suffix('"')).ok_or(format!("Invalid line {}", i + 1))?;
+        if let Some(name) = name {
+            if entry.contains_key(name) {
+                entries.push(std::mem::take(&mut entry));
+            }
+            current = Some(name);
+        }
+        let name = current.ok_or(format!("Invalid line {}", i + 1))?;
+        entry.entry(name).or_default().push_str(&po_unescape(val));
+    }
+    if !entry.is_empty() {
+        entries.push(entry);
+    }
+    let header = entries.iter().find(|x| !x.contains_key("msgctxt") && x.get("msgid").is_some_and(|x| x.is_empty()))
+        .ok_or("Missing po header")?;
+    let language = header.get("msgstr").and_then(|x| x.lines().find_map(|x| x.strip_prefix("Language:")))
+        .map(|x| Crc::from_string(x.trim())).ok_or("Missing language in po header")?;
+    let english = language.key() == LangStrings::KEY_ENGLISH;
+    let rows = entries.iter().filter_map(|x| {
+        let key = x.get("msgctxt")?;
+        let val = if english { x.get("msgid") } else { x.get("msgstr") }?;
+        if val.is_empty() || (english && *val == *key) {
+            warn!("Untranslated string {}", key);
+            return None;
+        }
+        Some((Crc::from_string(key), vec![val.clone()]))
+    }).collect();
+    Ok(Table { languages: vec![language], rows })
+}
+
+fn lang_blocks_mut<'a>(blocks: &'a mut [&mut SubBlocks]) -> Vec<(&'a Crc, &'a mut LangStrings)> {
+    blocks.iter_mut().flat_map(|x| x.block_headers.iter().zip(x.blocks.iter_mut())).filter_map(|(h, b)| match b {
+        SubBlock::LangStrings(val) => Some((&h.key, val)),
+        _ => None
+    }).collect()
+}
+
+/// Adds a key to the string keys and every language, languages without a value get the value of the fallback language
+pub fn add_key(keys: &mut StringKeys, blocks: &mut [&mut SubBlocks], key: Crc, values: &[(Crc, String)], fallback: &Crc) -> Result<(), String> {
+    if keys.vals.iter().any(|x| x.key == key) {
+        return Err(format!("Key {} already exists", key.to_string()));
+    }
+    let default = values.iter().find(|(lang, _)| lang == fallback).map(|(_, x)| x.clone());
+    let mut langs = lang_blocks_mut(blocks);
+    for (lang, _) in values {
+        if !langs.iter().any(|(x, _)| *x == lang) {
+            return Err(format!("No language {}", lang.to_string()));
+        }
+    }
+    for (lang, block) in langs.iter_mut() {
+        let val = values.iter().find(|(x, _)| x == *lang).map(|(_, x)| x.clone()).or(default.clone()).unwrap_or_else(|| {
+            warn!("No {} string for {}", lang.to_string(), key.to_string());
+            String::new()
+        });
+        block.strings.push(val);
+    }
+    let mut vals = keys.vals.iter().map(|x| x.key.clone()).collect::<Vec<_>>();
+    vals.push(key);
+    *keys = StringKeys::new(vals);
+    Ok(())
+}
+
+/// Strings are stored by position so only the key table changes
+pub fn rename_key(keys: &mut StringKeys, key: &Crc, new_key: Crc) -> Result<(), String> {
+    if keys.vals.iter().any(|x| x.key == new_key) {
+        return Err(format!("Key {} already exists", new_key.to_string()));
+    }
+    let val = keys.vals.iter_mut().find(|x| x.key == *key).ok_or(format!("No key {}", key.to_string()))?;
+    val.key = new_key;
+    Ok(())
+}
+
+pub fn remove_key(keys: &mut StringKeys, blocks: &mut [&mut SubBlocks], key: &Crc) -> Result<(), String> {
+    let i = keys.vals.iter().position(|x| x.key == *key).ok_or(format!("No key {}", key.to_string()))?;
+    for (_, block) in lang_blocks_mut(blocks) {
+        if i < block.strings.len() {
+            block.strings.remove(i);
+        }
+    }
+    let mut vals = keys.vals.iter().map(|x| x.key.clone()).collect::<Vec<_>>();
+    vals.remove(i);
+    *keys = StringKeys::new(vals);
+    Ok(())
+}
+
+/// Adds a language block with the strings of an existing language, or empty strings. The name is added to the debug strings
+/// so it resolves when the file is parsed again, a level's block is then read as a language if the --level-info has it
+pub fn add_language(keys: &StringKeys, blocks: &mut SubBlocks, strings: &mut Strings, lang: Crc, copy_from: Option<&Crc>) -> Result<(), String> {
+    let Some(name) = lang.str() else {
+        return Err("Language names can't be given as a crc".to_string());
+    };
+    if blocks.block_headers.iter().any(|x| x.key == lang) {
+        return Err(format!("Language {} already exists", name));
+    }
+    let strings_ = match copy_from {
+        Some(src) => blocks.block_headers.iter().zip(&blocks.blocks).find_map(|(h, b)| match b {
+            SubBlock::LangStrings(val) if h.key == *src => Some(val.strings.clone()),
+            _ => None
+        }).ok_or(format!("No language {}", src.to_string()))?,
+        None => vec![String::new(); keys.vals.len()],
+    };
+    if !strings.strings.iter().any(|x| x == name) {
+        strings.strings.push(name.to_string());
+    }
+    blocks.block_headers.push(SubBlocksBlockHeader { key: lang.clone(), ..Default::default() });
+    blocks.blocks.push(SubBlock::LangStrings(LangStrings { strings: strings_ }));
+    blocks.header.block_num = blocks.blocks.len() as u32;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> Table {
+        Table {
+            languages: vec![Crc::from_string("English"), Crc::from_string("French")],
+            rows: vec![
+                (Crc::from_string("KEY_A"), vec!["Plain".to_string(), "Simple".to_string()]),
+                (Crc::from_string("KEY_B"), vec!["Comma, \"quote\"".to_string(), "Two\nlines\\".to_string()]),
+                (Crc::Key(0x12345678), vec!["Tab\there".to_string(), String::new()]),
+            ],
+        }
+    }
+
+    fn blocks() -> SubBlocks {
+        let mut blocks = SubBlocks::default();
+        for (lang, strings) in [("English", ["a", "b"]), ("French", ["c", "d"])] {
+            blocks.block_headers.push(SubBlocksBlockHeader { key: Crc::from_string(lang), ..Default::default() });
+            blocks.blocks.push(SubBlock::LangStrings(LangStrings { strings: strings.map(String::from).to_vec() }));
+        }
+        blocks
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let orig = table();
+        let val = from_csv(&to_csv(&orig)).unwrap();
+        assert_eq!(val.languages, orig.languages);
+        assert_eq!(val.rows, orig.rows);
+        assert!(from_csv("key,English\r\nKEY_A\r\n").is_err());
+    }
+
+    #[test]
+    fn po_round_trip() {
+        let orig = table();
+        for (i, lang) in orig.languages.iter().enumerate() {
+            let val = from_po(&to_po(&orig, lang).unwrap()).unwrap();
+            assert_eq!(val.languages, vec![lang.clone()]);
+            let rows = orig.rows.iter().filter(|(_, x)| !x[i].is_empty()).map(|(k, x)| (k.clone(), vec![x[i].clone()])).collect::<Vec<_>>();
+            assert_eq!(val.rows, rows);
+        }
+    }
+
+    #[test]
+    fn import_table() {
+        let mut keys = StringKeys::new(vec![Crc::from_string("KEY_A"), Crc::from_string("KEY_C")]);
+        let mut blocks = blocks();
+        let report = import(&table(), &mut keys, &mut [&mut blocks], true);
+        assert_eq!(report.missing, vec![Crc::from_string("KEY_C")]);
+        assert_eq!(report.extra.len(), 2);
+        assert_eq!(keys.vals.len(), 4);
+        let val = export(&keys, &[&blocks]);
+        assert_eq!(val.rows[0].1, vec!["Plain", "Simple"]);
+        assert_eq!(val.rows[1].1, vec!["b", "d"]);
+        // the language without a string gets the English one
+        assert_eq!(val.rows[3].1, vec!["Tab\there", "Tab\there"]);
+    }
+}
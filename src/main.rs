@@ -1,21 +1,36 @@
 use std::{
-    collections::{HashSet, VecDeque}, fs, path::{Path, PathBuf}
+    collections::{HashMap, HashSet, VecDeque}, fs, iter::zip, path::{Path, PathBuf}
 };
 use audio::AudioTable;
 use zerocopy::LE;
-use log::error;
-use clap::{Parser, Args};
+use log::{error, info, warn};
+use clap::{Parser, Args, Subcommand};
+use itertools::Itertools;
+use rayon::prelude::*;
 
 mod audio;
+mod bnk;
 mod types;
 mod pak;
 mod pak_alt;
 mod bin;
 mod level_alt;
+mod lazy;
 mod level_info;
 mod level;
 mod lua_stuff;
 mod read_write;
+mod collision;
+mod guids;
+mod query;
+mod schema;
+mod gamemode;
+mod strings;
+mod crack;
+mod sounds;
+mod subtitles;
+mod locale;
+mod info;
 
 use level::Level;
 use level_info::LevelInfo;
@@ -45,6 +60,9 @@ struct CliArgs {
     #[command(flatten)]
     command: Commands,
 
+    #[command(subcommand)]
+    cmd: Option<Cmd>,
+
     /// Decompile lua files when loading a level
     #[arg(long)]
     lua_decomp: bool,
@@ -68,6 +86,27 @@ struct CliArgs {
     /// Don't dump to zip files 
     #[arg(short='z', long)]
     no_zip: bool,
+
+    /// Number of threads used for the inputs and the assets inside them, defaults to the number of cores
+    #[arg(long, global = true)]
+    jobs: Option<usize>,
+
+    /// GameObjs schema to use instead of the built in one
+    #[arg(long, global = true)]
+    schema: Option<PathBuf>,
+
+    /// level_info (.dat, dumped folder or zip) with the gamemodes and languages of the levels, their masks are then shown as gamemode names
+    /// and blocks of its added languages are read as strings
+    #[arg(long, global = true)]
+    level_info: Option<PathBuf>,
+
+    /// Extra string dictionaries (one string per line) used to resolve crcs
+    #[arg(long, global = true)]
+    dict: Vec<PathBuf>,
+
+    /// Format values are dumped in, dumps in any of the formats can be loaded
+    #[arg(long, value_enum, default_value_t, global = true)]
+    format: read_write::Format,
 }
 
 #[derive(Args, Debug)]
@@ -85,74 +124,1154 @@ struct Commands {
     alt_comp: bool
 }
 
-fn parse<A: AsRef<Path>, B: AsRef<Path>>(src: A, dest: B, args: &Commands, parsed: &mut HashSet<PathBuf>) {
+#[derive(Subcommand, Debug)]
+enum Cmd {
+    /// Generate a collision shape for a mesh from its vertex data
+    Collision(CollisionArgs),
+    /// Inspect or edit the level objects by GUID
+    Object(ObjectArgs),
+    /// Find level objects matching a filter, e.g. `type == "spawn_emitter" && GameModeMask & 2`
+    Query(QueryArgs),
+    /// Build, check and export the GameObjs type registry
+    #[command(subcommand)]
+    Schema(SchemaCmd),
+    /// List the assets loaded in each gamemode, or add / remove an asset and its dependencies
+    Gamemode(GamemodeArgs),
+
+    /// List the crcs of a level that don't resolve to a known string
+    Strings(StringsArgs),
+
+    /// Try to find the strings of unresolved crcs from wordlists and name patterns
+    Crack(CrackArgs),
+
+    /// Print the crc of strings, reads lines from stdin if no strings are given
+    Hash {
+        strings: Vec<String>,
+        /// Crc (or string) to start the hash from, as used for the `*` secondary texture keys
+        #[arg(short, long)]
+        mask: Option<String>,
+    },
+    /// Look up the strings of crcs, reads lines from stdin if no keys are given
+    Unhash {
+        keys: Vec<String>,
+    },
+
+    /// Export or import the localized strings of a level or level_info as csv or gettext po
+    #[command(subcommand)]
+    Locale(LocaleCmd),
+    /// Add, rename or remove localized string keys in every language at once, or add a language
+    StringKey(StringKeyArgs),
+    /// Register new levels and gamemodes in a level_info
+    LevelInfo(LevelInfoArgs),
+    /// List, extract or repack the wems of a wwise soundbank
+    Bnk(BnkArgs),
+    /// Find the soundbanks holding the sound events of an object, or of every event in the audio table
+    SoundBanks(SoundBanksArgs),
+    /// List the subtitle blocks of a level, export one as srt or ass, or import an edited file back
+    Subtitles(SubtitlesArgs),
+    /// Extract textures, meshes, lua scripts or sub blocks from a compiled level without dumping all of it
+    Extract(ExtractArgs),
+    /// Print a summary of a level or level_info: platform, table counts, sub blocks, languages and gamemodes
+    Info {
+        /// Input level (.PAK, dumped folder or zip) or level_info (.dat, dumped folder or zip)
+        input: PathBuf,
+
+        /// Print the report as json
+        #[arg(short, long)]
+        json: bool,
+    },
+}
+
+#[derive(Args, Debug)]
+struct ExtractArgs {
+    /// Input level (.PAK)
+    input: PathBuf,
+
+    /// Output folder, laid out like a dumped level, defaults to the level name
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Textures to extract
+    #[arg(short, long)]
+    texture: Vec<String>,
+
+    /// Meshes to extract, along with their skeleton
+    #[arg(short, long)]
+    mesh: Vec<String>,
+
+    /// Lua scripts to extract, the .lua extension is optional
+    #[arg(short, long)]
+    lua: Vec<String>,
+
+    /// Sub blocks to extract, e.g. Level or English
+    #[arg(short, long)]
+    subblock: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+struct SubtitlesArgs {
+    /// Input level (.PAK, dumped folder or zip)
+    input: PathBuf,
+
+    #[command(subcommand)]
+    action: SubtitlesAction,
+
+    /// Output level, defaults to overwriting the input
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum SubtitlesAction {
+    List,
+    /// Write a subtitle block to a .srt or .ass file
+    Export {
+        name: String,
+        file: PathBuf,
+    },
+    /// Replace a subtitle block with a .srt or .ass file, values the file doesn't hold are kept from the block
+    Import {
+        name: String,
+        file: PathBuf,
+    },
+}
+
+#[derive(Args, Debug)]
+struct SoundBanksArgs {
+    /// WWiseIDTable (.bin or dumped .audio.json, .audio.yaml, ...)
+    table: PathBuf,
+
+    /// Folder with the soundbanks, defaults to the folder of the table
+    #[arg(short, long)]
+    banks: Option<PathBuf>,
+
+    /// Level (.PAK, dumped folder or zip) with the object
+    #[arg(short, long, requires = "guid")]
+    level: Option<PathBuf>,
+
+    /// GUID of the object, e.g. a class, the objects it references are included
+    #[arg(short, long, requires = "level")]
+    guid: Option<u32>,
+
+    /// GUID of the gamemode object, banks in its ModeSpecificBanks count as loaded
+    #[arg(short = 'm', long, requires = "level")]
+    gamemode: Option<u32>,
+
+    /// Add the required banks to the ModeSpecificBanks of the gamemode object
+    #[arg(short, long, requires = "gamemode")]
+    add: bool,
+
+    /// Output level when adding banks, defaults to overwriting the input
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct BnkArgs {
+    /// Input soundbank (.bnk)
+    input: PathBuf,
+
+    #[command(subcommand)]
+    action: BnkAction,
+
+    /// WWiseIDTable (.bin or dumped .audio.json, .audio.yaml, ...) used to name the ids in the bank
+    #[arg(short, long)]
+    table: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum BnkAction {
+    /// Print the wems, events and sounds of the bank as json
+    List,
+    /// Write every embedded wem as <ID>.wem along with a bank.json listing
+    Extract {
+        /// Output folder, defaults to the bank name
+        output: Option<PathBuf>,
+    },
+    /// Replace embedded wems with the <ID>.wem files in a folder, they must have the same format
+    Repack {
+        wems: PathBuf,
+        /// Output, defaults to overwriting the input
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Args, Debug)]
+struct LevelInfoArgs {
+    /// Input level_info (.dat, dumped folder or zip)
+    input: PathBuf,
+
+    #[command(subcommand)]
+    action: LevelInfoAction,
+
+    /// Output, defaults to overwriting the input
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum LevelInfoAction {
+    /// Add a level, its name and description strings are added as <NAME>_name and <NAME>_desc
+    AddLevel {
+        /// Level file name, at most 31 characters
+        #[arg(short, long)]
+        name: String,
+        /// Gamemodes the level can be played in
+        #[arg(short, long, value_delimiter = ',')]
+        gamemodes: Vec<String>,
+        #[arg(short, long, default_value_t = 0)]
+        dlc: u32,
+        /// Displayed name, defaults to the level name
+        #[arg(short, long)]
+        title: Option<String>,
+        #[arg(long, default_value = "")]
+        description: String,
+    },
+    /// Add a gamemode, its name and description strings are added as <KEY>_name and <KEY>_desc
+    AddGamemode {
+        #[arg(short, long)]
+        key: String,
+        /// Displayed name, defaults to the key
+        #[arg(short, long)]
+        title: Option<String>,
+        #[arg(long, default_value = "")]
+        description: String,
+    },
+}
+
+#[derive(Args, Debug)]
+struct StringKeyArgs {
+    /// Input level or level_info (.PAK / .dat, dumped folder or zip)
+    input: PathBuf,
+
+    #[command(subcommand)]
+    action: StringKeyAction,
+
+    /// Output, defaults to overwriting the input
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum StringKeyAction {
+    /// Add a key, values are given as LANGUAGE=TEXT
+    Add {
+        key: String,
+        #[arg(value_parser = parse_key_val)]
+        values: Vec<(String, String)>,
+        /// Language whose value is used for the languages that aren't given
+        #[arg(short, long, default_value = "English")]
+        fallback: String,
+    },
+    Rename {
+        key: String,
+        new_key: String,
+    },
+    Remove {
+        key: String,
+    },
+    /// Add a language block, with the strings of an existing language or empty ones
+    AddLanguage {
+        name: String,
+        #[arg(short, long)]
+        copy_from: Option<String>,
+    },
+}
+
+fn parse_key_val(val: &str) -> Result<(String, String), String> {
+    val.split_once('=').map(|(a, b)| (a.to_string(), b.to_string())).ok_or(format!("Expected LANGUAGE=TEXT, got {}", val))
+}
+
+#[derive(Subcommand, Debug)]
+enum LocaleCmd {
+    /// Write every language to a csv file, or a single language to a po file
+    Export {
+        /// Input level or level_info (.PAK / .dat, dumped folder or zip)
+        input: PathBuf,
+        /// Output .csv or .po file
+        output: PathBuf,
+        /// Language of the po file
+        #[arg(short, long, default_value = "English")]
+        language: String,
+    },
+    /// Update the strings from a csv or po file, matching them by key
+    Import {
+        /// Input level or level_info (.PAK / .dat, dumped folder or zip)
+        input: PathBuf,
+        /// The .csv or .po file
+        table: PathBuf,
+        /// Output, defaults to overwriting the input
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Add the keys that are only in the table, languages missing them get the English string
+        #[arg(short, long)]
+        add: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SchemaCmd {
+    /// Gather the type definitions of levels into a schema file
+    Build {
+        /// Levels to gather the types from
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        /// Output schema file
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Check the objects of a level against the schema
+    Validate {
+        input: PathBuf,
+    },
+    /// Write a JSON Schema for dumped GameObjs files
+    JsonSchema {
+        output: PathBuf,
+    },
+}
+
+#[derive(Args, Debug)]
+struct CollisionArgs {
+    /// Input level (.PAK, dumped folder or zip)
+    input: PathBuf,
+
+    /// Output level, compiled if it ends in .PAK, zipped if it ends in .zip
+    output: PathBuf,
+
+    /// Mesh to generate the collision for
+    #[arg(short, long)]
+    mesh: String,
+
+    /// Mesh to copy the unknown shape fields from, defaults to the mesh itself
+    #[arg(short, long)]
+    template: Option<String>,
+
+    /// Type of collision shape to generate
+    #[arg(short, long, value_enum, default_value_t = collision::CollisionKind::Hull)]
+    kind: collision::CollisionKind,
+}
+
+#[derive(Args, Debug)]
+struct ObjectArgs {
+    /// Input level (.PAK, dumped folder or zip)
+    input: PathBuf,
+
+    #[command(subcommand)]
+    action: ObjectAction,
+
+    /// Output level, defaults to overwriting the input
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum ObjectAction {
+    /// Print an object, or a single field of it, as json
+    Get {
+        guid: u32,
+        field: Option<String>,
+    },
+    /// Set a field of an object, the value is given as json
+    Set {
+        guid: u32,
+        field: String,
+        value: String,
+    },
+    /// Add a new object of an existing type with default values
+    Add {
+        #[arg(name = "TYPE")]
+        kind: String,
+        #[arg(short, long, default_value_t = 0)]
+        layer: u32,
+    },
+    /// Copy an object under a new GUID
+    Clone {
+        guid: u32,
+    },
+    /// Delete an object
+    Delete {
+        guid: u32,
+    },
+    /// Copy an object and everything it references from another level, GUIDs already in use are remapped
+    Import {
+        source: PathBuf,
+        guid: u32,
+        /// Layer for the imported objects whose layer isn't in this level
+        #[arg(short, long)]
+        layer: Option<u32>,
+    },
+    /// Copy a type definition from another level, merging it with the existing definition
+    ImportType {
+        source: PathBuf,
+        #[arg(name = "TYPE")]
+        kind: String,
+    },
+}
+
+#[derive(Args, Debug)]
+struct QueryArgs {
+    /// Input level (.PAK, dumped folder or zip)
+    input: PathBuf,
+
+    /// Filter over the object fields plus guid, type and layer. Supports == != < <= > >= ~= (contains) & | ^ && || ! and parentheses,
+    /// strings are compared as crcs
+    #[arg(default_value = "true")]
+    filter: String,
+
+    /// Fields to show, all fields are shown in json output if not given
+    #[arg(short, long, value_delimiter = ',')]
+    fields: Vec<String>,
+
+    /// Print the matches as json instead of a table
+    #[arg(short, long)]
+    json: bool,
+
+    /// Also search the effect objects
+    #[arg(short, long)]
+    effects: bool,
+}
+
+#[derive(Args, Debug)]
+struct GamemodeArgs {
+    /// Input level (.PAK, dumped folder or zip)
+    input: PathBuf,
+
+    #[command(subcommand)]
+    action: GamemodeAction,
+
+    /// Output level, defaults to overwriting the input
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+/// Gamemode of the level, the name needs the level to have gamemode names (dumped with --level-info)
+#[derive(Args, Debug)]
+#[group(multiple = false)]
+struct GamemodeArg {
+    /// Index of the gamemode's mask bit
+    #[arg(long)]
+    index: Option<usize>,
+
+    /// GUID of the gamemode object
+    #[arg(long)]
+    guid: Option<u32>,
+
+    /// Gamemode name
+    #[arg(long)]
+    name: Option<String>,
+}
+
+impl GamemodeArg {
+    fn gamemode(self) -> Option<gamemode::Gamemode> {
+        self.index.map(gamemode::Gamemode::Index)
+            .or(self.guid.map(gamemode::Gamemode::Guid))
+            .or(self.name.map(gamemode::Gamemode::Name))
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum GamemodeAction {
+    /// Print the assets of a gamemode as json, or the number of assets in every gamemode if none is given
+    List {
+        #[command(flatten)]
+        gamemode: GamemodeArg,
+    },
+    /// Add an asset and everything it depends on to a gamemode,
+    /// assets are given by name (optionally prefixed with meshes/, textures/, animations/ or effects/) or object GUID
+    Add {
+        #[command(flatten)]
+        gamemode: GamemodeArg,
+        asset: String,
+    },
+    /// Remove an asset from a gamemode, along with the dependencies nothing else in the gamemode uses
+    Remove {
+        #[command(flatten)]
+        gamemode: GamemodeArg,
+        asset: String,
+    },
+}
+
+#[derive(Args, Debug)]
+struct StringsArgs {
+    /// Input level (.PAK, dumped folder or zip)
+    input: PathBuf,
+
+    /// Write every known string, including the ones found in the level and the --dict files, to a dictionary file
+    #[arg(short, long)]
+    export: Option<PathBuf>,
+
+    /// Print every location of the unresolved crcs as json
+    #[arg(short, long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct CrackArgs {
+    /// Level whose unresolved crcs to crack
+    input: Option<PathBuf>,
+
+    /// Crcs to crack, in addition to the ones of the level
+    #[arg(short, long)]
+    key: Vec<String>,
+
+    /// Words to fill the `*` of the patterns with, the parts of the known names are used if none are given
+    #[arg(short, long)]
+    wordlist: Vec<PathBuf>,
+
+    /// Patterns to try, `*` is replaced by a word and `#` by a two digit number, e.g. `CH_hum_*_01` or `ANM_*`
+    #[arg(short, long)]
+    pattern: Vec<String>,
+
+    /// Also try patterns made from the known names, with one part of the name replaced
+    #[arg(short, long)]
+    derive: bool,
+
+    /// Write every known string, including the hits, to a dictionary file
+    #[arg(short, long)]
+    export: Option<PathBuf>,
+}
+
+/// The given values, or the lines of stdin if there are none
+fn stdin_or(vals: Vec<String>) -> Vec<String> {
+    if !vals.is_empty() {
+        return vals;
+    }
+    std::io::stdin().lines().map(|x| x.unwrap().trim().to_string()).filter(|x| !x.is_empty()).collect()
+}
+
+fn is_level_info<P: AsRef<Path>>(path: P) -> bool {
+    let path = path.as_ref();
+    if path.is_file() {
+        path.extension().is_some_and(|x| x == "dat")
+    } else if path.extension().is_some_and(|x| x == "zip") {
+        Reader::new_zip(path).join("index").find_val().is_some()
+    } else {
+        Reader::new(path).join("index").find_val().is_some()
+    }
+}
+
+fn load_level_info<P: AsRef<Path>>(path: P) -> LevelInfo {
+    let path = path.as_ref();
+    if path.is_file() && path.extension().is_some_and(|x| x == "dat") {
+        LevelInfo::parse(path)
+    } else if path.extension().is_some_and(|x| x == "zip") {
+        LevelInfo::from_file(Reader::new_zip(path))
+    } else {
+        LevelInfo::from_file(Reader::new(path))
+    }
+}
+
+/// The folder a file is in, a bare file name is in the current folder
+fn folder(path: &Path) -> &Path {
+    match path.parent() {
+        Some(x) if !x.as_os_str().is_empty() => x,
+        _ => Path::new("."),
+    }
+}
+
+fn load_audio_table<P: AsRef<Path>>(path: P) -> AudioTable {
+    let path = path.as_ref();
+    if path.extension().and_then(|x| x.to_str()).and_then(read_write::Format::from_extension).is_some() {
+        AudioTable::from_file(path)
+    } else {
+        AudioTable::parse(path)
+    }
+}
+
+fn store_level_info<P: AsRef<Path>>(level_info: &LevelInfo, path: P) {
+    let path = path.as_ref();
+    match path.extension().and_then(|x| x.to_str()) {
+        Some("dat") => level_info.dump::<LE, _>(path),
+        Some("zip") => level_info.to_file(Writer::new(path, true)),
+        _ => level_info.to_file(Writer::new(path, false)),
+    }
+}
+
+/// Reads a level, the `level_info` names the gamemodes of levels that don't name their own
+fn load_level<P: AsRef<Path>>(path: P, level_info: Option<&LevelInfo>) -> level_alt::Level {
+    let path = path.as_ref();
+    if path.with_extension("PAK").is_file() {
+        level_alt::Level::parse(path, level_info)
+    } else if path.extension().is_some_and(|x| x == "zip") {
+        level_alt::Level::from_file(Reader::new_zip(path), level_info)
+    } else if path.is_dir() {
+        level_alt::Level::from_file(Reader::new(path), level_info)
+    } else {
+        panic!("Could not load level {:?}", path)
+    }
+}
+
+/// The level objects, and the effect objects if asked for.
+/// Compiled levels are read lazily so none of their assets are decompressed
+fn load_objects<P: AsRef<Path>>(path: P, effects: bool, level_info: Option<&LevelInfo>) -> (Option<types::GameObjs>, HashMap<types::Crc, types::GameObjs>) {
+    let path = path.as_ref();
+    if path.with_extension("PAK").is_file() {
+        let level = lazy::LazyLevel::open(path, level_info).unwrap_or_else(|e| panic!("{}", e));
+        (level.game_objs(), if effects { level.effects() } else { HashMap::new() })
+    } else {
+        let level = load_level(path, level_info);
+        let objs = level.sub_blocks1.blocks.into_iter().find_map(|x| match x {
+            types::SubBlock::GameObjs(val) => Some(val),
+            _ => None
+        });
+        (objs, if effects { level.effects } else { HashMap::new() })
+    }
+}
+
+/// The subtitle blocks of a level, only those blocks are parsed for compiled levels
+fn load_subtitles<P: AsRef<Path>>(path: P, level_info: Option<&LevelInfo>) -> Vec<(types::Crc, types::SSA)> {
+    let path = path.as_ref();
+    if path.with_extension("PAK").is_file() {
+        let level = lazy::LazyLevel::open(path, level_info).unwrap_or_else(|e| panic!("{}", e));
+        (0..2).flat_map(|i| level.sub_block_headers(i))
+            .filter(|x| x.key.str().is_some_and(|x| x.ends_with(".ssa")))
+            .filter_map(|x| match level.sub_block(&x.key) {
+                Some((_, types::SubBlock::SSA(val))) => Some((x.key, val)),
+                _ => None
+            }).collect()
+    } else {
+        let level = load_level(path, level_info);
+        let blocks = [&level.sub_blocks1, &level.sub_blocks2];
+        subtitles::list(&blocks).into_iter().map(|x| {
+            let val = subtitles::get(&blocks, &x).unwrap().clone();
+            (x, val)
+        }).collect()
+    }
+}
+
+fn store_level<P: AsRef<Path>>(level: &level_alt::Level, path: P) {
+    let path = path.as_ref();
+    match path.extension().and_then(|x| x.to_str()) {
+        Some("PAK") | Some("BIN") => level.dump::<LE, _>(path),
+        Some("zip") => level.to_file(Writer::new(path, true)),
+        _ => level.to_file(Writer::new(path, false)),
+    }
+}
+
+fn run(cmd: Cmd, level_info: Option<&LevelInfo>) {
+    match cmd {
+        Cmd::Collision(args) => {
+            let mut level = load_level(&args.input, level_info);
+            if let Err(e) = collision::generate(&mut level, &args.mesh.as_str().into(), args.template.as_deref().map(|x| x.into()), args.kind) {
+                error!("{}", e);
+                return;
+            }
+            store_level(&level, &args.output);
+        }
+        Cmd::Object(args) => {
+            if let ObjectAction::Get { guid, field } = &args.action {
+                let objs = load_objects(&args.input, false, level_info).0.expect("Level has no objects");
+                let obj = objs.find(*guid).unwrap_or_else(|| panic!("No object with guid {}", guid));
+                let val = match field {
+                    Some(field) => objs.get_field(*guid, &field.as_str().into()).unwrap_or_else(|| panic!("Object {} has no field {}", guid, field)).to_json(),
+                    None => serde_json::json!({
+                        "type": objs.obj_headers[obj].key.to_string(),
+                        "layer": objs.obj_headers[obj].layer,
+                        "fields": zip(objs.fields_of(&objs.obj_headers[obj].key), &objs.objs[obj])
+                            .map(|(t, v)| (t.key.to_string(), v.to_json())).collect::<serde_json::Map<_, _>>()
+                    })
+                };
+                println!("{}", serde_json::to_string_pretty(&val).unwrap());
+                return;
+            }
+            let mut level = load_level(&args.input, level_info);
+            let mut registry = guids::GuidRegistry::new(&level);
+            let objs = level.game_objs_mut().expect("Level has no objects");
+            match args.action {
+                ObjectAction::Get { .. } => unreachable!(),
+                ObjectAction::Set { guid, field, value } => {
+                    let field: types::Crc = field.as_str().into();
+                    let kind = objs.get_field(guid, &field).unwrap_or_else(|| panic!("Object {} has no field {}", guid, field.to_string())).kind();
+                    let value = serde_json::from_str(&value).unwrap_or_else(|_| serde_json::Value::String(value));
+                    objs.set_field(guid, &field, types::BaseTypes::from_json(&value, kind));
+                },
+                ObjectAction::Add { kind, layer } => {
+                    let kind = kind.as_str().into();
+                    let schema = schema::SCHEMA.lock().unwrap();
+                    if !schema.add_type(objs, &kind) {
+                        if schema.types.is_empty() {
+                            panic!("Unknown object type {}, the schema has no types, build one with `schema build` and pass it with --schema", kind.to_string());
+                        }
+                        panic!("Unknown object type {}", kind.to_string());
+                    }
+                    let guid = registry.alloc().unwrap_or_else(|e| panic!("{}", e));
+                    objs.add_object(&kind, layer, guid);
+                    info!("Added object {}", guid);
+                },
+                ObjectAction::Clone { guid } => {
+                    let new_guid = registry.alloc().unwrap_or_else(|e| panic!("{}", e));
+                    objs.clone_object(guid, new_guid);
+                    info!("Cloned object {} to {}", guid, new_guid);
+                },
+                ObjectAction::Delete { guid } => {
+                    objs.remove_object(guid).unwrap_or_else(|| panic!("No object with guid {}", guid));
+                },
+                ObjectAction::Import { source, guid, layer } => {
+                    let source = load_level(source, level_info);
+                    let map = guids::import_objects(&mut level, source.game_objs().expect("Source level has no objects"), guid, layer)
+                        .unwrap_or_else(|e| panic!("{}", e));
+                    for (old, new) in map {
+                        info!("Remapped {} to {}", old, new);
+                    }
+                },
+                ObjectAction::ImportType { source, kind } => {
+                    let source = load_level(source, level_info);
+                    let objs = level.game_objs_mut().expect("Level has no objects");
+                    objs.import_type(source.game_objs().expect("Source level has no objects"), &kind.as_str().into())
+                        .unwrap_or_else(|e| panic!("{}", e));
+                },
+            }
+            store_level(&level, args.output.as_ref().unwrap_or(&args.input));
+        }
+        Cmd::Query(args) => {
+            let filter = query::Expr::parse(&args.filter).unwrap_or_else(|e| panic!("Invalid filter: {}", e));
+            let (objs, effects) = load_objects(&args.input, args.effects, level_info);
+            let mut sources = objs.into_iter().map(|x| ("Level".to_string(), x)).collect::<Vec<_>>();
+            sources.extend(effects.into_iter().sorted_by(|a, b| a.0.cmp(&b.0)).map(|(k, v)| (k.to_string(), v)));
+            let mut rows = vec![];
+            for (name, objs) in sources {
+                for obj in query::objects(&objs).into_iter().filter(|x| filter.matches(x)) {
+                    let mut row = query::select(&obj, &args.fields);
+                    if args.effects {
+                        row["source"] = serde_json::Value::String(name.clone());
+                    }
+                    rows.push(row);
+                }
+            }
+            if args.json {
+                println!("{}", serde_json::to_string_pretty(&rows).unwrap());
+            } else {
+                let mut fields = args.fields.clone();
+                if args.effects {
+                    fields.insert(0, "source".to_string());
+                }
+                println!("{}", query::table(&rows, &fields));
+            }
+            info!("{} matching objects", rows.len());
+        }
+        Cmd::Schema(SchemaCmd::Build { inputs, output }) => {
+            let mut schema = schema::Schema::default();
+            for input in inputs {
+                let level = load_level(input, level_info);
+                level.game_objs().into_iter().chain(level.effects.values()).for_each(|objs| schema.merge(objs));
+            }
+            info!("{} types", schema.types.len());
+            fs::write(output, serde_json::to_vec_pretty(&schema).unwrap()).unwrap();
+        }
+        Cmd::Schema(SchemaCmd::Validate { input }) => {
+            let level = load_level(input, level_info);
+            let schema = schema::SCHEMA.lock().unwrap();
+            if schema.types.is_empty() {
+                error!("The schema has no types, build one from the vanilla levels with `schema build` and pass it with --schema");
+                return;
+            }
+            let mut valid = true;
+            for (name, objs) in level.game_objs().map(|x| ("Level".to_string(), x)).into_iter()
+                .chain(level.effects.iter().sorted_by_key(|(k, _)| *k).map(|(k, v)| (k.to_string(), v))) {
+                for e in schema.validate(objs) {
+                    error!("{}: {}", name, e);
+                    valid = false;
+                }
+            }
+            if valid {
+                info!("No problems found");
+            }
+        }
+        Cmd::Schema(SchemaCmd::JsonSchema { output }) => {
+            fs::write(output, serde_json::to_vec_pretty(&schema::SCHEMA.lock().unwrap().json_schema()).unwrap()).unwrap();
+        }
+        Cmd::Strings(args) => {
+            let level = load_level(&args.input, level_info);
+            let found = strings::unresolved(&level);
+            if args.json {
+                let val = found.iter().map(|(k, v)| (format!("0x{:08X}", k), v)).collect::<std::collections::BTreeMap<_, _>>();
+                println!("{}", serde_json::to_string_pretty(&val).unwrap());
+            } else {
+                for (key, locations) in &found {
+                    println!("0x{:08X} {:6}  {}", key, locations.len(), locations[0]);
+                }
+            }
+            info!("{} unresolved crcs", found.len());
+            if let Some(path) = args.export {
+                let n = strings::export_dict(path);
+                info!("Exported {} strings", n);
+            }
+        }
+        Cmd::Crack(args) => {
+            let mut targets = args.key.iter().map(|x| types::Crc::from_string(x).key()).collect::<HashSet<_>>();
+            if let Some(input) = &args.input {
+                targets.extend(strings::unresolved(&load_level(input, level_info)).into_keys());
+            }
+            assert!(!targets.is_empty(), "Nothing to crack, give a level or some keys");
+            let known = types::STRING_LOOKUP.lock().unwrap().values().cloned().collect::<Vec<_>>();
+            let words = if args.wordlist.is_empty() {
+                crack::name_parts(&known)
+            } else {
+                args.wordlist.iter().flat_map(crack::load_wordlist).collect()
+            };
+            let mut patterns = args.pattern.clone();
+            if patterns.is_empty() {
+                patterns.push("*".to_string());
+            }
+            if args.derive {
+                patterns.extend(crack::derive_patterns(&known));
+            }
+            info!("Trying {} patterns with {} words against {} crcs", patterns.len(), words.len(), targets.len());
+            let hits = crack::Cracker::new(targets, &words).run(&patterns);
+            for (key, name) in &hits {
+                println!("0x{:08X} {}", key, name);
+            }
+            info!("{} crcs resolved", hits.len());
+            if let Some(path) = args.export {
+                let n = strings::export_dict(path);
+                info!("Exported {} strings", n);
+            }
+        }
+        Cmd::Hash { strings, mask } => {
+            let mask = mask.map(|x| types::Crc::from_string(&x).key());
+            for string in stdin_or(strings) {
+                println!("0x{:08X} {}", types::hash_string(string.as_bytes(), mask), string);
+            }
+        }
+        Cmd::Unhash { keys } => {
+            let unhasher = strings::Unhasher::new();
+            for key in stdin_or(keys) {
+                let Some(val) = strings::parse_key(&key) else {
+                    error!("Invalid key {}", key);
+                    continue;
+                };
+                println!("0x{:08X} {}", val, unhasher.get(val).as_deref().unwrap_or("?"));
+            }
+        }
+        Cmd::Locale(LocaleCmd::Export { input, output, language }) => {
+            let table = if is_level_info(&input) {
+                let level_info = load_level_info(&input);
+                locale::export(&level_info.string_keys, &[&level_info.locale_strings])
+            } else {
+                let level = load_level(&input, level_info);
+                locale::export(&level.string_keys, &[&level.sub_blocks1, &level.sub_blocks2])
+            };
+            let data = if output.extension().is_some_and(|x| x == "po") {
+                locale::to_po(&table, &language.as_str().into()).unwrap_or_else(|e| panic!("{}", e))
+            } else {
+                locale::to_csv(&table)
+            };
+            fs::write(output, data).unwrap();
+            info!("Exported {} strings in {} languages", table.rows.len(), table.languages.len());
+        }
+        Cmd::Locale(LocaleCmd::Import { input, table, output, add }) => {
+            let data = fs::read_to_string(&table).unwrap();
+            let table = if table.extension().is_some_and(|x| x == "po") {
+                locale::from_po(&data)
+            } else {
+                locale::from_csv(&data)
+            }.unwrap_or_else(|e| panic!("Invalid table: {}", e));
+            let output = output.as_ref().unwrap_or(&input);
+            let report = if is_level_info(&input) {
+                let mut level_info = load_level_info(&input);
+                let report = locale::import(&table, &mut level_info.string_keys, &mut [&mut level_info.locale_strings], add);
+                store_level_info(&level_info, output);
+                report
+            } else {
+                let mut level = load_level(&input, level_info);
+                let report = locale::import(&table, &mut level.string_keys, &mut [&mut level.sub_blocks1, &mut level.sub_blocks2], add);
+                store_level(&level, output);
+                report
+            };
+            for key in &report.missing {
+                warn!("No string for {}", key.to_string());
+            }
+            for key in &report.extra {
+                if add {
+                    info!("Added {}", key.to_string());
+                } else {
+                    warn!("Unknown key {}", key.to_string());
+                }
+            }
+            for lang in &report.languages {
+                warn!("Unknown language {}", lang.to_string());
+            }
+        }
+        Cmd::StringKey(args) => {
+            let edit = |keys: &mut types::StringKeys, blocks: &mut [&mut types::SubBlocks], strings: &mut types::Strings| match &args.action {
+                StringKeyAction::Add { key, values, fallback } => {
+                    let values = values.iter().map(|(k, v)| (k.as_str().into(), v.clone())).collect::<Vec<_>>();
+                    locale::add_key(keys, blocks, key.as_str().into(), &values, &fallback.as_str().into())
+                },
+                StringKeyAction::Rename { key, new_key } => locale::rename_key(keys, &key.as_str().into(), new_key.as_str().into()),
+                StringKeyAction::Remove { key } => locale::remove_key(keys, blocks, &key.as_str().into()),
+                StringKeyAction::AddLanguage { name, copy_from } => {
+                    locale::add_language(keys, blocks[0], strings, name.as_str().into(), copy_from.as_deref().map(|x| x.into()).as_ref())
+                },
+            };
+            let output = args.output.as_ref().unwrap_or(&args.input);
+            if is_level_info(&args.input) {
+                let mut level_info = load_level_info(&args.input);
+                edit(&mut level_info.string_keys, &mut [&mut level_info.locale_strings], &mut level_info.strings).unwrap_or_else(|e| panic!("{}", e));
+                store_level_info(&level_info, output);
+            } else {
+                let mut level = load_level(&args.input, level_info);
+                edit(&mut level.string_keys, &mut [&mut level.sub_blocks1, &mut level.sub_blocks2], &mut level.pak_strings).unwrap_or_else(|e| panic!("{}", e));
+                store_level(&level, output);
+            }
+        }
+        Cmd::LevelInfo(args) => {
+            let mut level_info = load_level_info(&args.input);
+            match args.action {
+                LevelInfoAction::AddLevel { name, gamemodes, dlc, title, description } => {
+                    let gamemodes = gamemodes.iter().map(|x| x.as_str().into()).collect::<Vec<_>>();
+                    level_info.add_level(&name, &gamemodes, dlc, title.as_deref().unwrap_or(&name), &description)
+                },
+                LevelInfoAction::AddGamemode { key, title, description } => {
+                    level_info.add_gamemode(&key, title.as_deref().unwrap_or(&key), &description)
+                },
+            }.unwrap_or_else(|e| panic!("{}", e));
+            store_level_info(&level_info, args.output.as_ref().unwrap_or(&args.input));
+        }
+        Cmd::Bnk(args) => {
+            let mut bank = bnk::Bank::parse(&args.input);
+            let names = args.table.as_ref().map(load_audio_table).map(|x| x.names()).unwrap_or_default();
+            match args.action {
+                BnkAction::List => println!("{}", serde_json::to_string_pretty(&bank.listing(&names)).unwrap()),
+                BnkAction::Extract { output } => {
+                    let output = output.unwrap_or_else(|| args.input.with_extension(""));
+                    bank.extract(&output, &names);
+                    info!("Extracted {} wems to {}", bank.index().len(), output.display());
+                }
+                BnkAction::Repack { wems, output } => {
+                    let wems = fs::read_dir(&wems).unwrap().map(|x| x.unwrap().path())
+                        .filter(|x| x.extension().is_some_and(|x| x.eq_ignore_ascii_case("wem")))
+                        .filter_map(|x| x.file_stem().unwrap().to_str().unwrap().parse::<u32>().ok().map(|id| (id, fs::read(&x).unwrap())))
+                        .collect::<HashMap<_, _>>();
+                    let n = bank.replace_wems(&wems).unwrap_or_else(|e| panic!("{}", e));
+                    info!("Replaced {} wems", n);
+                    bank.dump(output.as_ref().unwrap_or(&args.input));
+                }
+            }
+        }
+        Cmd::SoundBanks(args) => {
+            let table = load_audio_table(&args.table);
+            let banks = sounds::load_banks(args.banks.as_deref().unwrap_or_else(|| folder(&args.table)));
+            let found = sounds::event_banks(&banks);
+            let report = if let (Some(path), Some(guid)) = (&args.level, args.guid) {
+                let objs = load_objects(path, false, level_info).0.expect("Level has no objects");
+                let events = sounds::object_events(&objs, &table, guid);
+                let report = sounds::report(&table, &events, &found, &sounds::loaded_banks(&objs, args.gamemode));
+                if args.add {
+                    let mut level = load_level(path, level_info);
+                    let objs = level.game_objs_mut().unwrap();
+                    let n = sounds::add_banks(objs, args.gamemode.unwrap(), &report.required).unwrap_or_else(|e| panic!("{}", e));
+                    info!("Added {} banks to gamemode {}", n, args.gamemode.unwrap());
+                    store_level(&level, args.output.as_ref().unwrap_or(path));
+                }
+                report
+            } else {
+                let events = table.events.iter().map(|x| x.key.clone()).collect::<Vec<_>>();
+                sounds::report(&table, &events, &found, &HashSet::new())
+            };
+            if !report.missing.is_empty() {
+                warn!("{} events aren't in any of the {} banks", report.missing.len(), banks.len());
+            }
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        }
+        Cmd::Subtitles(args) => {
+            let is_ass = |file: &Path| file.extension().is_some_and(|x| x.eq_ignore_ascii_case("ass"));
+            match args.action {
+                SubtitlesAction::List => {
+                    for (name, _) in load_subtitles(&args.input, level_info) {
+                        println!("{}", name.to_string());
+                    }
+                }
+                SubtitlesAction::Export { name, file } => {
+                    let key = name.as_str().into();
+                    let (_, ssa) = load_subtitles(&args.input, level_info).into_iter().find(|(x, _)| *x == key)
+                        .unwrap_or_else(|| panic!("No subtitle block {}", name));
+                    fs::write(&file, if is_ass(&file) { subtitles::to_ass(&ssa, &name) } else { subtitles::to_srt(&ssa) }).unwrap();
+                }
+                SubtitlesAction::Import { name, file } => {
+                    let mut level = load_level(&args.input, level_info);
+                    let data = fs::read_to_string(&file).unwrap();
+                    let mut blocks = [&mut level.sub_blocks1, &mut level.sub_blocks2];
+                    let ssa = subtitles::get_mut(&mut blocks, &name.as_str().into())
+                        .unwrap_or_else(|| panic!("No subtitle block {}", name));
+                    *ssa = if is_ass(&file) { subtitles::from_ass(&data, ssa) } else { subtitles::from_srt(&data, ssa) }
+                        .unwrap_or_else(|e| panic!("{}", e));
+                    info!("Imported {} subtitles", ssa.vals.len());
+                    store_level(&level, args.output.as_ref().unwrap_or(&args.input));
+                }
+            }
+        }
+        Cmd::Extract(args) => {
+            if args.texture.is_empty() && args.mesh.is_empty() && args.lua.is_empty() && args.subblock.is_empty() {
+                panic!("Nothing to extract, give at least one --texture, --mesh, --lua or --subblock");
+            }
+            let level = lazy::LazyLevel::open(&args.input, level_info).unwrap_or_else(|e| panic!("{}", e));
+            let writer = Writer::new(args.output.unwrap_or_else(|| args.input.file_stem().unwrap().into()), false);
+            for name in &args.texture {
+                let key: types::Crc = name.as_str().into();
+                let tex = level.texture(&key).unwrap_or_else(|| panic!("No texture {}", name));
+                tex.to_file(writer.join("textures").join(key.to_string()), &level.gamemodes);
+            }
+            for name in &args.mesh {
+                let key: types::Crc = name.as_str().into();
+                let mesh = level.mesh(&key).unwrap_or_else(|| panic!("No mesh {}", name));
+                mesh.to_file(&writer, &key, &level.gamemodes);
+            }
+            let scripts = args.lua.iter().map(|x| if x.ends_with(".lua") { x.clone() } else { format!("{}.lua", x) });
+            let names = scripts.chain(args.subblock).collect::<Vec<_>>();
+            if !names.is_empty() {
+                let keys = level.string_keys();
+                for name in names {
+                    let key: types::Crc = name.as_str().into();
+                    let (block, val) = level.sub_block(&key).unwrap_or_else(|| panic!("No sub block {}", name));
+                    val.to_file(writer.join(format!("sub_blocks{}", block + 1)).join(key.to_string()), &keys, &level.gamemodes);
+                }
+            }
+        }
+        Cmd::Info { input, json } => {
+            // only compiled files have a platform, zips start with PK\x03\x04 which would pass for a big endian header
+            let (val, text) = if is_level_info(&input) {
+                let platform = input.extension().is_some_and(|x| x == "dat").then(|| info::platform(&input, 4)).flatten();
+                let report = info::LevelInfoReport::new(&load_level_info(&input), platform);
+                (serde_json::to_value(&report).unwrap(), report.text())
+            } else {
+                let report = if input.with_extension("PAK").is_file() {
+                    info::LevelReport::from_lazy(&lazy::LazyLevel::open(&input, level_info).unwrap_or_else(|e| panic!("{}", e)), info::platform(input.with_extension("BIN"), 6))
+                } else {
+                    info::LevelReport::new(&load_level(&input, level_info), None)
+                };
+                (serde_json::to_value(&report).unwrap(), report.text())
+            };
+            println!("{}", if json { serde_json::to_string_pretty(&val).unwrap() } else { text });
+        }
+        Cmd::Gamemode(args) => {
+            let mut level = load_level(&args.input, level_info);
+            let (gamemode, asset, enabled) = match args.action {
+                GamemodeAction::List { gamemode } => {
+                    if let Some(mode) = gamemode.gamemode() {
+                        let bit = mode.bit(&level);
+                        println!("{}", serde_json::to_string_pretty(&gamemode::group(&gamemode::assets(&level, bit))).unwrap());
+                        return;
+                    }
+                    for (i, guid, counts) in gamemode::summary(&level) {
+                        println!("{:2} {:16} {:10} {}", i, gamemode::name(&level, i), guid, gamemode::Asset::CATEGORIES.iter()
+                            .map(|x| format!("{} {}", counts.get(x).unwrap_or(&0), x)).join(", "));
+                    }
+                    return;
+                }
+                GamemodeAction::Add { gamemode, asset } => (gamemode, asset, true),
+                GamemodeAction::Remove { gamemode, asset } => (gamemode, asset, false),
+            };
+            let bit = gamemode.gamemode().expect("A gamemode is needed, given by --index, --guid or --name").bit(&level);
+            let asset = gamemode::Asset::resolve(&level, &asset).unwrap_or_else(|| panic!("Unknown asset {}", asset));
+            let changed = if enabled {
+                gamemode::add(&mut level, bit, &asset)
+            } else {
+                gamemode::remove(&mut level, bit, &asset)
+            };
+            for asset in &changed {
+                info!("{} {}/{}", if enabled { "Added" } else { "Removed" }, asset.category(), asset.name());
+            }
+            info!("{} assets updated in gamemode {}", changed.len(), gamemode::name(&level, bit));
+            store_level(&level, args.output.as_ref().unwrap_or(&args.input));
+        }
+    }
+}
+
+type Job<'a> = Box<dyn FnOnce() + Send + 'a>;
+
+/// The conversion of a dumped level or level_info, if `src` is one
+fn dump_job<'a>(src: &Path, ext: &str, out: PathBuf, dump: bool, zip: bool, level_info: Option<&'a LevelInfo>) -> Option<Job<'a>> {
+    let reader = if ext == "zip" {
+        Reader::new_zip(src)
+    } else if src.is_dir() {
+        Reader::new(src)
+    } else {
+        return None;
+    };
+    if reader.join("index").find_val().is_some() {
+        Some(Box::new(move || {
+            let val = LevelInfo::from_file(reader);
+            if dump {
+                val.to_file(Writer::new(out, zip));
+            } else {
+                val.dump::<LE, _>(out);
+            }
+        }))
+    } else if reader.join("pak_header").find_val().is_some() {
+        Some(Box::new(move || {
+            let level = level_alt::Level::from_file(reader, level_info);
+            if dump {
+                level.to_file(Writer::new(out, zip));
+            } else {
+                level.dump::<LE, _>(out)
+            }
+        }))
+    } else {
+        None
+    }
+}
+
+/// Finds what to do with an input, the conversions are returned so separate inputs can run in parallel.
+/// Crcs are named from the strings of every level read so far, so the strings of compiled inputs are read here,
+/// before any conversion runs, to name them the same whatever order the inputs finish in
+fn parse<'a, A: AsRef<Path>, B: AsRef<Path>>(src: A, dest: B, args: &Commands, level_info: Option<&'a LevelInfo>, parsed: &mut HashSet<PathBuf>, jobs: &mut Vec<Job<'a>>) {
     let mut q = VecDeque::from(vec![(PathBuf::new(), src.as_ref().to_path_buf())]);
     let dest = dest.as_ref();
+    let (compile, dump, alt_comp) = (args.compile, args.dump, args.alt_comp);
+    let zip = *types::ZIP.lock().unwrap();
     while let Some((name, src)) = q.pop_front() {
         let mut raw_name = src.file_name().unwrap().to_str().unwrap().split('.');
         let name = name.join(raw_name.next().unwrap());
         let ext = raw_name.collect::<Vec<_>>().join(".");
+        let out = dest.join(&name);
         if src.with_extension("PAK").is_file() && !parsed.contains(&src.with_extension("PAK")) {
             parsed.insert(src.with_extension("PAK"));
-            if args.compile {
-                Level::parse(src).dump::<LE, _>(dest.join(name));
-            } else if args.alt_comp {
-                level_alt::Level::parse(src).dump::<LE, _>(dest.join(name));
-            } else {
-                level_alt::Level::parse(src).to_file(Writer::new(dest.join(name), *types::ZIP.lock().unwrap()));
+            // only the headers and strings are read
+            if let Err(e) = lazy::LazyLevel::open(&src, None) {
+                error!("{}", e);
+                continue;
             }
+            jobs.push(Box::new(move || if compile {
+                Level::parse(src, level_info).dump::<LE, _>(out);
+            } else if alt_comp {
+                level_alt::Level::parse(src, level_info).dump::<LE, _>(out);
+            } else {
+                level_alt::Level::parse(src, level_info).to_file(Writer::new(out, zip));
+            }));
         } else if src.file_name().unwrap() == "level_info.dat" {
             parsed.insert(src.clone());
-            let level_info = LevelInfo::parse(src);
-            if args.compile {
-                level_info.dump::<LE, _>(dest.join(name));
+            let val = LevelInfo::parse(src);
+            jobs.push(Box::new(move || if compile {
+                val.dump::<LE, _>(out);
             } else {
-                level_info.to_file(Writer::new(dest.join(name), *types::ZIP.lock().unwrap()));
-            }
+                val.to_file(Writer::new(out, zip));
+            }));
         } else if !src.with_extension("PAK").is_file() && src.with_extension("bin").is_file() && ext == "bin" {
             parsed.insert(src.with_extension("bin"));
-            let table: AudioTable = AudioTable::parse(src);
-            if args.compile {
-                table.dump::<LE, _>(dest.join(name))
-            } else {
-                table.to_file(dest.join(name));
-            }
-        } else if ext == "audio.json" {
+            jobs.push(Box::new(move || {
+                let mut table: AudioTable = AudioTable::parse(&src);
+                if compile {
+                    table.dump::<LE, _>(out)
+                } else {
+                    table.set_event_banks(&sounds::event_banks(&sounds::load_banks(folder(&src))));
+                    table.to_file(out);
+                }
+            }));
+        } else if ext.strip_prefix("audio.").and_then(read_write::Format::from_extension).is_some() {
             parsed.insert(src.clone());
-            let table = AudioTable::from_file(src);
-            if args.dump {
-                table.to_file(dest.join(name));
-            } else {
-                table.dump::<LE, _>(dest.join(name))
-            }
-        } else if {
-            if let Some(reader) = (ext == "zip").then(|| Reader::new_zip(&src))
-                .or(src.is_dir().then(|| Reader::new(&src))) {
-                let name = name.clone();
-                if reader.join("index.json").is_file() {
-                    let level_info = LevelInfo::from_file(reader);
-                    if args.dump {
-                        level_info.to_file(Writer::new(dest.join(name), *types::ZIP.lock().unwrap()));
-                    } else {
-                        level_info.dump::<LE, _>(dest.join(name));
-                    }
-                    true
-                } else if reader.join("pak_header.json").is_file() {
-                    let level = level_alt::Level::from_file(reader);
-                    if args.dump {
-                        level.to_file(Writer::new(dest.join(name), *types::ZIP.lock().unwrap()));
-                    } else {
-                        level.dump::<LE, _>(dest.join(name))
-                    }
-                    true
+            jobs.push(Box::new(move || {
+                let table = AudioTable::from_file(src);
+                if dump {
+                    table.to_file(out);
                 } else {
-                    false
+                    table.dump::<LE, _>(out)
                 }
-            } else {
-                false
-            }
-        } {
+            }));
+        } else if let Some(job) = dump_job(&src, &ext, out.clone(), dump, zip, level_info) {
             parsed.insert(src.clone());
+            jobs.push(job);
         } else if src.is_dir(){
             parsed.insert(src.clone());
             for path in fs::read_dir(&src).unwrap().map(|x| x.unwrap().path()) {
@@ -193,6 +1312,7 @@ fn main() {
     *types::RECOMP_LUA.lock().unwrap() = args.lua_recomp;
     *types::ANIM_TABLES.lock().unwrap() = !args.no_anim_table;
     *types::ZIP.lock().unwrap() = !args.no_zip;
+    *types::FORMAT.lock().unwrap() = args.format;
     if let Some(compression) = args.compression {
         *types::COMPRESSION.lock().unwrap() = flate2::Compression::new(compression);
     }
@@ -200,10 +1320,32 @@ fn main() {
         *types::UNLUAC.lock().unwrap() = unluac;
     }
 
+    if let Some(jobs) = args.jobs {
+        rayon::ThreadPoolBuilder::new().num_threads(jobs).build_global().unwrap();
+    }
+
+    if let Some(schema) = args.schema {
+        *schema::SCHEMA.lock().unwrap() = serde_json::from_slice(&fs::read(schema).unwrap()).unwrap();
+    }
+
+    for path in args.dict {
+        let n = strings::load_dict(path);
+        info!("Loaded {} strings", n);
+    }
+
+    let level_info = args.level_info.map(load_level_info);
+
+    if let Some(cmd) = args.cmd {
+        run(cmd, level_info.as_ref());
+        return;
+    }
+
     let exe_dir = std::env::current_exe().unwrap().parent().unwrap().to_owned();
     let output: PathBuf = args.output.map(|x| x.into()).unwrap_or(exe_dir);
     let mut parsed = HashSet::new();
+    let mut jobs = vec![];
     for input in args.input {
-        parse(input, output.clone(), &args.command, &mut parsed);
+        parse(input, output.clone(), &args.command, level_info.as_ref(), &mut parsed, &mut jobs);
     }
+    jobs.into_par_iter().for_each(|job| job());
 }
\ No newline at end of file
@@ -4,7 +4,7 @@ use zerocopy::{ByteOrder, BE};
 use serde::{Serialize, Deserialize};
 
 use lotrc_rs_proc::OrderedData;
-use super::types::{BaseTypes, OrderedData, Vector4, Matrix4x4, OrderedDataVec, Vector2, Crc, Vector3};
+use super::types::{gamemode_mask, BaseTypes, OrderedData, Vector4, Matrix4x4, OrderedDataVec, Vector2, Crc, Vector3};
 
 #[derive(Debug, Default, Clone, OrderedData, Serialize, Deserialize)]
 pub struct Header {
@@ -157,6 +157,7 @@ pub struct Obj0{
 #[derive(Debug, Default, Clone, OrderedData, Serialize, Deserialize)]
 pub struct MeshInfo {
     pub key: Crc,
+    #[serde(with = "gamemode_mask")]
     pub gamemodemask: i32,
     pub mat_offset: u32,
     pub buffer_info_offset: u32, // pointer to obj2, uses mat_num of sequential objects
@@ -751,6 +752,7 @@ pub struct IBuffInfo {
 #[derive(Debug, Default, Clone, OrderedData, Serialize, Deserialize)]
 pub struct TextureInfo {
     pub key: Crc,
+    #[serde(with = "gamemode_mask")]
     pub gamemodemask: i32,
     pub asset_key: Crc,
     pub asset_type: u32,
@@ -787,6 +789,7 @@ pub struct TextureInfo {
 #[derive(Debug, Default, Clone, OrderedData, Serialize, Deserialize)]
 pub struct AnimationInfo {
     pub key: Crc,
+    #[serde(with = "gamemode_mask")]
     pub gamemodemask: i32,
     pub offset: u32,
     pub size: u32,
@@ -851,6 +854,7 @@ pub struct HkConstraintInfo {
 #[derive(Debug, Default, Clone, OrderedData, Serialize, Deserialize)]
 pub struct EffectInfo {
     pub key: Crc,
+    #[serde(with = "gamemode_mask")]
     pub gamemodemask: i32,
     pub offset: u32,
     pub size: u32,
@@ -922,6 +926,7 @@ pub struct IlluminationInfo {
 #[derive(Debug, Default, Clone, OrderedData, Serialize, Deserialize)]
 pub struct BlockAVal {
     pub unk_0: u32,
+    #[serde(with = "gamemode_mask")]
     pub gamemodemask: i32,
     pub key: Crc,
     pub unk_3: u32,
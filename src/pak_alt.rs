@@ -4,6 +4,7 @@ use log::warn;
 use zerocopy::ByteOrder;
 use serde::{Serialize, Deserialize};
 use crate::types::Crc;
+use crate::read_write::{PathStuff, Writer};
 
 use lotrc_rs_proc::OrderedData;
 use super::types::{OrderedData, Vector4, Matrix4x4, OrderedDataVec};
@@ -44,6 +45,17 @@ pub struct Mesh {
 }
 
 impl Mesh {
+    /// Writes the mesh to `meshes` and, for skinned meshes, its skeleton to `skeletons`.
+    /// The mask is named after the level's `gamemodes`
+    pub fn to_file(&self, writer: &Writer, key: &Crc, gamemodes: &[Crc]) -> Option<Skeleton> {
+        writer.join("meshes").join(key.to_string()).write_masked(self, gamemodes);
+        (!self.keys.is_empty()).then(|| {
+            let skeleton = Skeleton::new(self);
+            writer.join("skeletons").join(key.to_string()).write_val(&skeleton);
+            skeleton
+        })
+    }
+
     pub fn from_data<O: ByteOrder + 'static>(data: &[u8], offset: usize) -> Self {
         let info: MeshInfo = OrderedData::from_bytes::<O>(&data[offset..]);
         let indices: Vec<u32> = OrderedDataVec::from_bytes::<O>(&data[info.indices_offset as usize..], info.keys_num as usize);
@@ -113,7 +125,9 @@ impl Mesh {
             Shape::from_data::<O>(data, info.shape_offset as usize + i * ShapeInfo::size::<O>())
         ).collect();
 
-        let hk_constraint = (info.hk_constraint_offset != 0).then(|| HkConstraint::from_data::<O>(data, info.hk_constraint_offset as usize));
+        let hk_constraint = (info.hk_constraint_offset != 0).then(||
+            HkConstraint::from_data::<O>(data, info.hk_constraint_offset as usize).unwrap_or_else(|e| panic!("Mesh {}: {}", info.key.to_string(), e))
+        );
         let hk_constraint_datas: Vec<HkConstraintData> = OrderedDataVec::from_bytes::<O>(&data[info.hk_constraint_data_offset as usize..], info.hk_constraint_data_num as usize);
         
         let mats = HashSet::<u32>::from_iter(mat_order.iter().cloned()).into_iter().sorted().collect::<Vec<_>>();
@@ -643,6 +657,104 @@ impl Mesh {
     }
 }
 
+/// What the matrices stored in a skinned mesh are, found by comparing them with the reference pose in the mesh's HkConstraint
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BoneMatrices {
+    #[default]
+    Unknown,
+    BindPose,
+    InverseBindPose,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Bone {
+    pub name: Crc,
+    pub parent: Option<usize>,
+    pub parent_name: Option<Crc>,
+    /// The bone's matrix as stored in the mesh
+    pub matrix: Matrix4x4,
+    pub bind_pose: Option<Matrix4x4>,
+    pub inverse_bind_pose: Option<Matrix4x4>,
+}
+
+/// Bone hierarchy of a skinned mesh, built from the mesh keys (names), indices (parents) and matrices
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Skeleton {
+    pub matrices: BoneMatrices,
+    pub bones: Vec<Bone>,
+}
+
+impl Skeleton {
+    const TOLERANCE: f32 = 1e-3;
+
+    pub fn new(mesh: &Mesh) -> Self {
+        let name = |i: usize| mesh.keys.get(i).cloned().unwrap_or(Crc::Key(i as u32));
+        let matrices = Self::resolve(mesh);
+        let bones = zip(&mesh.indices, &mesh.matrices).enumerate().map(|(i, (parent, matrix))| {
+            let parent = (*parent != 0xFFFFFFFF).then_some(*parent as usize);
+            let inverse = || matrix.inverse().or_else(|| {
+                warn!("Singular matrix for bone {}", name(i).to_string());
+                None
+            });
+            let (bind_pose, inverse_bind_pose) = match matrices {
+                BoneMatrices::Unknown => (None, None),
+                BoneMatrices::BindPose => (Some(matrix.clone()), inverse()),
+                BoneMatrices::InverseBindPose => (inverse(), Some(matrix.clone())),
+            };
+            Bone {
+                name: name(i),
+                parent,
+                parent_name: parent.map(name),
+                matrix: matrix.clone(),
+                bind_pose,
+                inverse_bind_pose,
+            }
+        }).collect();
+        Self { matrices, bones }
+    }
+
+    /// World space reference pose of each named bone in the mesh's HkConstraint, its vals hold a hkQsTransform per bone relative to its parent
+    pub fn reference_pose(constraint: &HkConstraint) -> HashMap<Crc, Matrix4x4> {
+        let vals = constraint.vals.iter().map(|x| f32::from_bits(*x)).collect::<Vec<_>>();
+        let vector = |i: usize| Vector4 { x: vals[i], y: vals[i + 1], z: vals[i + 2], w: vals[i + 3] };
+        let mut world: Vec<Matrix4x4> = Vec::with_capacity(constraint.strings.len());
+        for (i, parent) in constraint.shorts.iter().enumerate().take(constraint.strings.len().min(vals.len() / 12)) {
+            let local = Matrix4x4::from_qs_transform(&vector(i * 12), &vector(i * 12 + 4), &vector(i * 12 + 8));
+            world.push(match world.get(*parent as usize) {
+                Some(parent) => local.mul(parent),
+                None => local,
+            });
+        }
+        zip(&constraint.strings, world).map(|((name, _), matrix)| (name.as_str().into(), matrix)).collect()
+    }
+
+    /// Every bone that has a reference pose has to agree on what the stored matrices are, either layout (rows or columns) is accepted
+    fn resolve(mesh: &Mesh) -> BoneMatrices {
+        let Some(constraint) = &mesh.hk_constraint else { return BoneMatrices::Unknown };
+        let pose = Self::reference_pose(constraint);
+        let mut found = HashSet::new();
+        for (key, matrix) in zip(&mesh.keys, &mesh.matrices) {
+            let Some(world) = pose.get(key) else { continue };
+            let matches = |m: &Matrix4x4| m.approx_eq(matrix, Self::TOLERANCE) || m.transpose().approx_eq(matrix, Self::TOLERANCE);
+            if matches(world) {
+                found.insert(BoneMatrices::BindPose);
+            } else if world.inverse().is_some_and(|x| matches(&x)) {
+                found.insert(BoneMatrices::InverseBindPose);
+            } else {
+                found.insert(BoneMatrices::Unknown);
+            }
+        }
+        match found.into_iter().exactly_one() {
+            Ok(val) => val,
+            Err(_) => BoneMatrices::Unknown,
+        }
+    }
+
+    pub fn bone_index(&self, name: &Crc) -> Option<usize> {
+        self.bones.iter().position(|x| x.name == *name)
+    }
+}
+
 #[derive(Debug, Default, Clone, OrderedData, Serialize, Deserialize)]
 pub struct Key2 {
     pub key: Crc,
@@ -662,12 +774,20 @@ pub struct HkConstraint {
 }
 
 impl HkConstraint {
-    pub fn from_data<O: ByteOrder + 'static>(data: &[u8], offset: usize) -> Self {
+    /// Only the kind 0 layout is known, other kinds are an error rather than being read with the wrong layout
+    pub fn from_data<O: ByteOrder + 'static>(data: &[u8], offset: usize) -> Result<Self, String> {
         let info: HkConstraintInfo = OrderedData::from_bytes::<O>(&data[offset..]);
-        if info.kind != 0 { panic!("Unknown & Unhandled HkConstraint type {} at offset {}", info.kind, offset); }
+        match info.kind {
+            0 => Self::from_data_kind0::<O>(data, info),
+            kind => Err(format!("Unknown HkConstraint type {} at offset {}", kind, offset)),
+        }
+    }
 
+    fn from_data_kind0<O: ByteOrder + 'static>(data: &[u8], info: HkConstraintInfo) -> Result<Self, String> {
         let shorts: Vec<u16> = OrderedDataVec::from_bytes::<O>(&data[info.shorts_offset as usize..], info.shorts_num as usize);
-        assert!(shorts[0] == 0xFFFF);
+        if shorts.first() != Some(&0xFFFF) {
+            return Err(format!("HkConstraint type 0 does not start with a root, got {:?}", shorts.first()));
+        }
 
         
         let string_offsets: Vec<u32> = OrderedDataVec::from_bytes::<O>(&data[info.strings_offset as usize..], info.strings_num as usize);
@@ -685,9 +805,9 @@ impl HkConstraint {
         let vals = OrderedDataVec::from_bytes::<O>(&data[info.vals_offset as usize..], info.vals_num as usize * 12);
         let vals2 = OrderedDataVec::from_bytes::<O>(&data[info.vals2_offset as usize..], info.vals2_num as usize * 42);
         let keys = OrderedDataVec::from_bytes::<O>(&data[info.keys2_offset as usize..], info.keys2_num as usize);
-        Self {
+        Ok(Self {
             info, shorts, strings, string_offsets, vals, vals2, keys
-        }
+        })
     }
 
     pub fn dump<O: ByteOrder + 'static>(&self, mut offset: usize, keys_offset: u32, keys_num: u32, infos: &mut DumpInfos) -> Vec<u8> {
@@ -802,9 +922,9 @@ impl Mat {
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Shape {
-    info: ShapeInfo,
-    extra: Option<ShapeExtra>,
-    hk_shapes: Vec<HkShape>,
+    pub info: ShapeInfo,
+    pub extra: Option<ShapeExtra>,
+    pub hk_shapes: Vec<HkShape>,
 }
 
 impl Shape {
@@ -1233,4 +1353,88 @@ pub struct DumpInfos {
     pub ibuff: Vec<IBuffInfo>,
     pub buffer: Vec<BufferInfo>,
     pub block2_offsets: Vec<u32>,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use zerocopy::LE;
+    use super::*;
+
+    fn constraint_data(constraint: &HkConstraint) -> (Vec<u8>, HkConstraintInfo) {
+        let mut infos = DumpInfos::default();
+        let offset = HkConstraintInfo::size::<LE>();
+        let vals = constraint.dump::<LE>(offset, 0, 0, &mut infos);
+        let info = infos.hk_constraint.pop().unwrap();
+        let mut data = vec![info.clone()].dump_bytes::<LE>();
+        data.extend(vals);
+        (data, info)
+    }
+
+    #[test]
+    fn constraint_round_trip() {
+        let constraint = HkConstraint {
+            shorts: vec![0xFFFF, 0, 1],
+            strings: vec![("root".to_string(), 0), ("spine".to_string(), 1), ("head".to_string(), 2)],
+            vals: (0..36).map(|x| if x % 12 == 11 { 0x7FC00001 } else { x }).collect(),
+            ..Default::default()
+        };
+        let (data, _) = constraint_data(&constraint);
+        let val = HkConstraint::from_data::<LE>(&data, 0).unwrap();
+        assert_eq!(val.shorts, constraint.shorts);
+        assert_eq!(val.strings, constraint.strings);
+        assert_eq!(val.vals, constraint.vals);
+        let json: HkConstraint = serde_json::from_str(&serde_json::to_string(&val).unwrap()).unwrap();
+        assert_eq!(json.vals, constraint.vals);
+    }
+
+    #[test]
+    fn unknown_constraint_kind() {
+        let constraint = HkConstraint { shorts: vec![0xFFFF], ..Default::default() };
+        let (mut data, mut info) = constraint_data(&constraint);
+        info.kind = 2;
+        info.to_bytes::<LE>(&mut data);
+        assert!(HkConstraint::from_data::<LE>(&data, 0).is_err());
+    }
+
+    fn skinned_mesh(store: impl Fn(&Matrix4x4) -> Matrix4x4) -> Mesh {
+        // root translated along x, child rotated 90 degrees around z and translated along y
+        let pose = [[1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0], [0.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.70710677, 0.70710677]];
+        let vals = pose.iter().flat_map(|p| p.iter().chain(&[1.0, 1.0, 1.0, 1.0])).map(|x: &f32| x.to_bits()).collect();
+        let constraint = HkConstraint {
+            shorts: vec![0xFFFF, 0],
+            strings: vec![("root".to_string(), 0), ("child".to_string(), 1)],
+            vals,
+            ..Default::default()
+        };
+        let world = Skeleton::reference_pose(&constraint);
+        let keys: Vec<Crc> = vec!["root".into(), "child".into()];
+        Mesh {
+            matrices: keys.iter().map(|x| store(&world[x])).collect(),
+            keys,
+            indices: vec![0xFFFFFFFF, 0],
+            hk_constraint: Some(constraint),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn skeleton_matrices() {
+        let world = Skeleton::reference_pose(&skinned_mesh(|x| x.clone()).hk_constraint.unwrap());
+        let child = world[&"child".into()].to_array();
+        assert!((child[3][0] - 1.0).abs() < 1e-6 && (child[3][1] - 2.0).abs() < 1e-6);
+        assert!((child[0][1] - 1.0).abs() < 1e-6);
+
+        let skeleton = Skeleton::new(&skinned_mesh(|x| x.clone()));
+        assert_eq!(skeleton.matrices, BoneMatrices::BindPose);
+        let identity = skeleton.bones[1].inverse_bind_pose.as_ref().unwrap().mul(&skeleton.bones[1].matrix).to_array();
+        assert!(identity.iter().enumerate().all(|(i, row)| row.iter().enumerate().all(|(j, x)| (x - if i == j { 1.0 } else { 0.0 }).abs() < 1e-5)));
+        assert_eq!(skeleton.bones[1].parent_name, Some("root".into()));
+
+        let skeleton = Skeleton::new(&skinned_mesh(|x| x.inverse().unwrap().transpose()));
+        assert_eq!(skeleton.matrices, BoneMatrices::InverseBindPose);
+        assert!(skeleton.bones[0].bind_pose.is_some());
+
+        let skeleton = Skeleton::new(&skinned_mesh(|x| x.mul(x)));
+        assert_eq!(skeleton.matrices, BoneMatrices::Unknown);
+        assert!(skeleton.bones.iter().all(|x| x.bind_pose.is_none() && x.inverse_bind_pose.is_none()));
+    }
+}
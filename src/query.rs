@@ -0,0 +1,307 @@
+use serde_json::{json, Map, Value};
+
+use super::types::{Crc, GameObjs};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(f64),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+const OPS: [&str; 13] = ["&&", "||", "==", "!=", "<=", ">=", "~=", "<", ">", "!", "&", "|", "^"];
+
+fn tokenize(src: &str) -> Result<Vec<Token>, String> {
+    let chars = src.chars().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '(' {
+            tokens.push(Token::LParen);
+            i += 1;
+        } else if c == ')' {
+            tokens.push(Token::RParen);
+            i += 1;
+        } else if c == '"' || c == '\'' {
+            let end = chars[i + 1..].iter().position(|x| *x == c).ok_or(format!("Unterminated string at {}", i))?;
+            tokens.push(Token::Str(chars[i + 1..i + 1 + end].iter().collect()));
+            i += end + 2;
+        } else if c.is_ascii_digit() || (c == '-' && chars.get(i + 1).is_some_and(|x| x.is_ascii_digit())) {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.') { i += 1; }
+            let val: String = chars[start..i].iter().collect();
+            let num = match val.strip_prefix("0x") {
+                Some(hex) => u32::from_str_radix(hex, 16).map(|x| x as f64).ok(),
+                None => val.parse::<f64>().ok(),
+            };
+            tokens.push(Token::Num(num.ok_or(format!("Invalid number {}", val))?));
+        } else if c.is_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.') { i += 1; }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if let Some(op) = OPS.iter().find(|op| chars[i..].starts_with(&op.chars().collect::<Vec<_>>())) {
+            tokens.push(Token::Op(op));
+            i += op.len();
+        } else {
+            return Err(format!("Unexpected character {:?} at {}", c, i));
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Val(Value),
+    Field(String),
+    Not(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek_op(&self, ops: &[&str]) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) if ops.contains(op) => Some(op),
+            _ => None
+        }
+    }
+
+    fn binary(&mut self, ops: &[&str], next: fn(&mut Self) -> Result<Expr, String>) -> Result<Expr, String> {
+        let mut lhs = next(self)?;
+        while let Some(op) = self.peek_op(ops) {
+            self.pos += 1;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(next(self)?));
+        }
+        Ok(lhs)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> { self.binary(&["||"], Self::and) }
+    fn and(&mut self) -> Result<Expr, String> { self.binary(&["&&"], Self::cmp) }
+    fn cmp(&mut self) -> Result<Expr, String> { self.binary(&["==", "!=", "<=", ">=", "<", ">", "~="], Self::bits) }
+    fn bits(&mut self) -> Result<Expr, String> { self.binary(&["&", "|", "^"], Self::unary) }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.peek_op(&["!"]).is_some() {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        let token = self.tokens.get(self.pos).cloned().ok_or("Unexpected end of query")?;
+        self.pos += 1;
+        match token {
+            Token::Num(val) => Ok(Expr::Val(json!(val))),
+            Token::Str(val) => Ok(Expr::Val(json!(val))),
+            Token::Ident(val) if val == "true" || val == "false" => Ok(Expr::Val(json!(val == "true"))),
+            Token::Ident(val) => Ok(Expr::Field(val)),
+            Token::LParen => {
+                let val = self.or()?;
+                match self.tokens.get(self.pos) {
+                    Some(Token::RParen) => { self.pos += 1; Ok(val) },
+                    _ => Err("Missing closing parenthesis".to_string())
+                }
+            },
+            token => Err(format!("Unexpected {:?}", token))
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let mut parser = Parser { tokens: tokenize(src)?, pos: 0 };
+        let val = parser.or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(val),
+            Some(token) => Err(format!("Unexpected {:?}", token)),
+        }
+    }
+
+    fn eval(&self, obj: &Value) -> Value {
+        match self {
+            Self::Val(val) => val.clone(),
+            Self::Field(name) => lookup(obj, name),
+            Self::Not(val) => json!(!truthy(&val.eval(obj))),
+            Self::Binary(op, lhs, rhs) => {
+                let lhs = lhs.eval(obj);
+                match *op {
+                    "&&" => return json!(truthy(&lhs) && truthy(&rhs.eval(obj))),
+                    "||" => return json!(truthy(&lhs) || truthy(&rhs.eval(obj))),
+                    _ => ()
+                }
+                let rhs = rhs.eval(obj);
+                match *op {
+                    "==" => json!(equals(&lhs, &rhs)),
+                    "!=" => json!(!equals(&lhs, &rhs)),
+                    "~=" => json!(contains(&lhs, &rhs)),
+                    "<" | "<=" | ">" | ">=" => match (lhs.as_f64(), rhs.as_f64()) {
+                        (Some(a), Some(b)) => json!(match *op { "<" => a < b, "<=" => a <= b, ">" => a > b, _ => a >= b }),
+                        _ => json!(false)
+                    },
+                    _ => match (lhs.as_f64(), rhs.as_f64()) {
+                        (Some(a), Some(b)) => {
+                            let (a, b) = (a as i64, b as i64);
+                            json!(match *op { "&" => a & b, "|" => a | b, _ => a ^ b })
+                        },
+                        _ => json!(0)
+                    },
+                }
+            }
+        }
+    }
+
+    pub fn matches(&self, obj: &Value) -> bool {
+        truthy(&self.eval(obj))
+    }
+}
+
+/// Fields are matched by crc so names are case insensitive and can be given as 0x keys
+fn lookup(obj: &Value, name: &str) -> Value {
+    if let Some(val) = obj.get(name) {
+        return val.clone();
+    }
+    let key = Crc::from_string(name).key();
+    obj["fields"].as_object().and_then(|fields|
+        fields.iter().find(|(k, _)| Crc::from_string(k).key() == key).map(|(_, v)| v.clone())
+    ).unwrap_or(Value::Null)
+}
+
+fn truthy(val: &Value) -> bool {
+    match val {
+        Value::Null => false,
+        Value::Bool(val) => *val,
+        Value::Number(val) => val.as_f64() != Some(0.0),
+        Value::String(val) => !val.is_empty(),
+        Value::Array(val) => !val.is_empty(),
+        Value::Object(val) => !val.is_empty(),
+    }
+}
+
+/// Strings compare as crcs, so "0x1234ABCD" matches the name it hashes from
+fn equals(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => Crc::from_string(a).key() == Crc::from_string(b).key(),
+        (Value::Bool(a), b) | (b, Value::Bool(a)) if !b.is_boolean() => *a == truthy(b),
+        _ => match (a.as_f64(), b.as_f64()) {
+            (Some(a), Some(b)) => a == b,
+            _ => a == b,
+        }
+    }
+}
+
+fn contains(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Array(vals), b) => vals.iter().any(|a| equals(a, b)),
+        (Value::String(a), Value::String(b)) => a.to_lowercase().contains(&b.to_lowercase()),
+        _ => equals(a, b)
+    }
+}
+
+/// Json view of every object, the same layout as the dumped level
+pub fn objects(objs: &GameObjs) -> Vec<Value> {
+    objs.obj_headers.iter().zip(&objs.objs).map(|(o, fs)| {
+        let ts = objs.fields_of(&o.key);
+        let guid = (0..fs.len()).find(|i| ts[*i].key.key() == GameObjs::GUID_FIELD).map(|i| fs[i].to_json()).unwrap_or(Value::Null);
+        json!({
+            "guid": guid,
+            "type": o.key.to_string(),
+            "layer": o.layer,
+            "fields": ts.iter().zip(fs).map(|(t, v)| (t.key.to_string(), v.to_json())).collect::<Map<_, _>>()
+        })
+    }).collect()
+}
+
+/// Restricts the objects to the requested fields, the guid type and layer are always kept
+pub fn select(obj: &Value, fields: &[String]) -> Value {
+    let mut val = Map::new();
+    for key in ["guid", "type", "layer"] {
+        val.insert(key.to_string(), obj[key].clone());
+    }
+    if fields.is_empty() {
+        val.insert("fields".to_string(), obj["fields"].clone());
+    } else {
+        for field in fields {
+            val.insert(field.clone(), lookup(obj, field));
+        }
+    }
+    Value::Object(val)
+}
+
+pub fn table(rows: &[Value], fields: &[String]) -> String {
+    let mut header = vec!["guid".to_string(), "type".to_string(), "layer".to_string()];
+    header.extend(fields.iter().cloned());
+    let cells = rows.iter().map(|row| header.iter().map(|key| match &row[key] {
+        Value::String(val) => val.clone(),
+        Value::Null => String::new(),
+        val => val.to_string(),
+    }).collect::<Vec<_>>()).collect::<Vec<_>>();
+    let widths = (0..header.len()).map(|i|
+        cells.iter().map(|row| row[i].len()).chain([header[i].len()]).max().unwrap()
+    ).collect::<Vec<_>>();
+    let line = |row: &[String]| row.iter().zip(&widths).map(|(x, w)| format!("{:w$}", x, w = w)).collect::<Vec<_>>().join("  ").trim_end().to_string();
+    let mut out = vec![line(&header), widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<_>>().join("  ")];
+    out.extend(cells.iter().map(|row| line(row)));
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn obj() -> Value {
+        json!({
+            "guid": 1234,
+            "type": "Spawn",
+            "layer": 0,
+            "fields": { "Name": "Balrog_Spawn", "Health": 250.0, "GameModeMask": 5, "Classes": ["Balrog", "0x00000001"], "Enabled": true }
+        })
+    }
+
+    fn check(src: &str) -> bool {
+        Expr::parse(src).unwrap_or_else(|e| panic!("{}: {}", src, e)).matches(&obj())
+    }
+
+    #[test]
+    fn tokens() {
+        assert_eq!(tokenize("a.b>=-1.5 && !'x y'").unwrap(), vec![
+            Token::Ident("a.b".to_string()), Token::Op(">="), Token::Num(-1.5), Token::Op("&&"), Token::Op("!"), Token::Str("x y".to_string())
+        ]);
+        assert_eq!(tokenize("0x10|(2)").unwrap(), vec![Token::Num(16.0), Token::Op("|"), Token::LParen, Token::Num(2.0), Token::RParen]);
+    }
+
+    #[test]
+    fn fields() {
+        assert!(check("type == 'Spawn'"));
+        assert!(check("guid == 1234 && layer == 0"));
+        // field names match by crc, so case and 0x keys don't matter
+        assert!(check("health > 200 && HEALTH <= 250"));
+        assert!(check("name ~= 'balrog'"));
+        assert!(check("Classes ~= 'balrog' && Classes ~= '0x00000001'"));
+        assert!(check("Enabled && !Missing"));
+    }
+
+    #[test]
+    fn precedence() {
+        assert!(check("GameModeMask & 4"));
+        assert!(!check("GameModeMask & 2"));
+        assert!(check("(GameModeMask & 4) == 4"));
+        assert!(check("false && false || true"));
+        assert!(!check("false && (false || true)"));
+        assert!(check("!false && true"));
+    }
+
+    #[test]
+    fn errors() {
+        for src in ["", "a ==", "(a == 1", "a == 1)", "'unterminated", "a = 1", "0xZZ"] {
+            assert!(Expr::parse(src).is_err(), "{}", src);
+        }
+    }
+}
@@ -3,8 +3,65 @@ use std::sync::{Arc, Mutex};
 use std::path::{Path, PathBuf};
 use std::fs;
 use itertools::Itertools;
+use serde::{Serialize, de::DeserializeOwned};
 use zip::{ZipArchive, ZipWriter, write::SimpleFileOptions};
 
+use super::types::{self, Crc, FORMAT};
+
+/// Formats the values of a dump can be written in, readers tell them apart by their extension
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    #[default]
+    Json,
+    Ron,
+    Yaml,
+    Msgpack,
+    Cbor,
+}
+
+impl Format {
+    pub const ALL: [Self; 5] = [Self::Json, Self::Ron, Self::Yaml, Self::Msgpack, Self::Cbor];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Ron => "ron",
+            Self::Yaml => "yaml",
+            Self::Msgpack => "msgpack",
+            Self::Cbor => "cbor",
+        }
+    }
+
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|x| x.extension() == ext)
+    }
+
+    pub fn to_vec<T: Serialize + ?Sized>(self, val: &T) -> Vec<u8> {
+        match self {
+            Self::Json => serde_json::to_vec_pretty(val).unwrap(),
+            Self::Ron => ron::ser::to_string_pretty(val, ron::ser::PrettyConfig::default()).unwrap().into_bytes(),
+            Self::Yaml => serde_yaml_ng::to_string(val).unwrap().into_bytes(),
+            // named so fields can still be skipped or defaulted
+            Self::Msgpack => rmp_serde::to_vec_named(val).unwrap(),
+            Self::Cbor => {
+                let mut out = vec![];
+                ciborium::into_writer(val, &mut out).unwrap();
+                out
+            }
+        }
+    }
+
+    pub fn deserialize<T: DeserializeOwned>(self, data: &[u8]) -> Result<T, String> {
+        match self {
+            Self::Json => serde_json::from_slice(data).map_err(|e| e.to_string()),
+            Self::Ron => ron::de::from_bytes(data).map_err(|e| e.to_string()),
+            Self::Yaml => serde_yaml_ng::from_slice(data).map_err(|e| e.to_string()),
+            Self::Msgpack => rmp_serde::from_slice(data).map_err(|e| e.to_string()),
+            Self::Cbor => ciborium::from_reader(data).map_err(|e| e.to_string()),
+        }
+    }
+}
+
 fn format_path(path: &Path) -> String {
     path.iter().map(|x| x.to_str().unwrap()).join("/")
 }
@@ -80,6 +137,29 @@ impl Reader {
         }
     }
 
+    /// Format of the file from its extension
+    pub fn format(&self) -> Option<Format> {
+        self.path().extension().and_then(|x| x.to_str()).and_then(Format::from_extension)
+    }
+
+    /// The file holding a value written with `Writer::write_val`, in whichever format it's in
+    pub fn find_val(&self) -> Option<(Format, Self)> {
+        if let Some(format) = self.format() {
+            return self.is_file().then(|| (format, self.with_path(self.path())));
+        }
+        Format::ALL.into_iter().map(|x| (x, self.with_extension(x.extension()))).find(|(_, x)| x.is_file())
+    }
+
+    pub fn read_val<T: DeserializeOwned>(&self) -> T {
+        let (format, reader) = self.find_val().unwrap_or_else(|| panic!("Could not find {:?}", self));
+        format.deserialize(&reader.read()).unwrap_or_else(|e| panic!("Could not read {:?}: {}", reader, e))
+    }
+
+    /// Reads a value whose gamemode masks may be named after `gamemodes`
+    pub fn read_masked<T: DeserializeOwned>(&self, gamemodes: &[Crc]) -> T {
+        types::with_gamemodes(gamemodes, || self.read_val())
+    }
+
     pub fn read(&self) -> Vec<u8> {
         match self {
             Self::File(path) => fs::read(path).unwrap(),
@@ -151,6 +231,17 @@ impl Writer {
         }
     }
 
+    /// Writes a value in the selected dump format, with that format's extension
+    pub fn write_val<T: Serialize + ?Sized>(&self, val: &T) {
+        let format = *FORMAT.lock().unwrap();
+        self.with_extension(format.extension()).write(&format.to_vec(val))
+    }
+
+    /// Writes a value with its gamemode masks named after `gamemodes`
+    pub fn write_masked<T: Serialize + ?Sized>(&self, val: &T, gamemodes: &[Crc]) {
+        types::with_gamemodes(gamemodes, || self.write_val(val))
+    }
+
     pub fn write(&self, contents: &[u8]) {
         match self {
             Self::File(path) => {
@@ -167,3 +258,122 @@ impl Writer {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use crate::types::Crc;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Val {
+        key: Crc,
+        vals: Vec<f32>,
+        name: Option<String>,
+        #[serde(default)]
+        extra: u32,
+    }
+
+    fn val() -> Val {
+        Val { key: Crc::from_string("Level"), vals: vec![1.5, -2.0], name: Some("a".to_string()), extra: 3 }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("lotrc_test_{}_{}", std::process::id(), name));
+        fs::remove_dir_all(&path).ok();
+        path
+    }
+
+    #[test]
+    fn formats_round_trip() {
+        for format in Format::ALL {
+            assert_eq!(Format::from_extension(format.extension()), Some(format));
+            assert_eq!(format.deserialize::<Val>(&format.to_vec(&val())), Ok(val()), "{:?}", format);
+        }
+        assert_eq!(Format::from_extension("txt"), None);
+    }
+
+    #[test]
+    fn find_by_extension() {
+        let path = temp_dir("find");
+        let writer = Writer::new(&path, false);
+        for format in Format::ALL {
+            writer.join(format.extension()).with_extension(format.extension()).write(&format.to_vec(&val()));
+        }
+        let reader = Reader::new(&path);
+        for format in Format::ALL {
+            // found without the extension, or read in the format of the one given
+            let (found, _) = reader.join(format.extension()).find_val().unwrap();
+            assert_eq!(found, format);
+            assert_eq!(reader.join(format.extension()).read_val::<Val>(), val());
+            assert_eq!(reader.join(format!("{0}.{0}", format.extension())).read_val::<Val>(), val());
+        }
+        assert!(reader.join("missing").find_val().is_none());
+        assert!(reader.join("json.yaml").find_val().is_none());
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn zip_round_trip() {
+        let path = temp_dir("zip");
+        {
+            let writer = Writer::new(path.join("dump"), true);
+            writer.join("a").with_extension("ron").write(&Format::Ron.to_vec(&val()));
+            writer.join("b/c").with_extension("msgpack").write(&Format::Msgpack.to_vec(&val()));
+        }
+        let reader = Reader::new_zip(path.join("dump.zip"));
+        assert_eq!(reader.join("a").read_val::<Val>(), val());
+        assert_eq!(reader.join("b").join("c").read_val::<Val>(), val());
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// A dumped GameObjs block as the dump writes it, with names only known by their crc and floats that aren't round
+    fn objs(path: &Path) -> serde_json::Value {
+        let val = serde_json::json!({
+            "gamemodemask": -1,
+            "types": [{
+                "name": "0x1A2B3C4D",
+                "size": 4,
+                "fields": [
+                    { "name": "GUID", "type": "GUID", "offset": 0 },
+                    { "name": "0x00C0FFEE", "type": "Float", "offset": 4 },
+                    { "name": "Position", "type": "Vector3", "offset": 8 },
+                    { "name": "Model", "type": "CRC", "offset": 20 },
+                ]
+            }],
+            "objs": [
+                { "type": "0x1A2B3C4D", "layer": 3, "fields": { "GUID": 12, "0x00C0FFEE": 0.1, "Position": [1.5, -0.00225, 1e10], "Model": "0xDEADBEEF" } },
+                { "type": "0x1A2B3C4D", "layer": 0, "fields": { "GUID": 13, "0x00C0FFEE": -7.0, "Position": [0.0, 1.0 / 3.0, -2.5], "Model": "0x0000002A" } },
+            ],
+        });
+        Writer::new(path, false).join("objs").with_extension("json").write(&serde_json::to_vec(&val).unwrap());
+        types::GameObjs::from_file(Reader::new(path).join("objs"), &[]).to_json(&[])
+    }
+
+    #[test]
+    fn objs_round_trip() {
+        let path = temp_dir("objs");
+        let val = objs(&path);
+        assert_eq!(val["objs"][0]["fields"]["Model"], "0xDEADBEEF");
+        let writer = Writer::new(&path, false);
+        for format in Format::ALL {
+            // compared once read back, json can parse the widened f32s an ulp off as f64 but not as f32
+            let name = format!("objs_{}", format.extension());
+            writer.join(&name).with_extension(format.extension()).write(&format.to_vec(&val));
+            assert_eq!(types::GameObjs::from_file(Reader::new(&path).join(&name), &[]).to_json(&[]), val, "{:?}", format);
+        }
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    // FORMAT is global, this is the only test that changes it
+    #[test]
+    fn write_in_selected_format() {
+        let path = temp_dir("write");
+        *FORMAT.lock().unwrap() = Format::Yaml;
+        Writer::new(&path, false).join("val").write_val(&val());
+        *FORMAT.lock().unwrap() = Format::Json;
+        assert!(path.join("val.yaml").is_file());
+        assert_eq!(Reader::new(&path).join("val").read_val::<Val>(), val());
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
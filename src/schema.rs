@@ -0,0 +1,217 @@
+use std::{collections::BTreeMap, iter::zip, sync::Mutex};
+use log::warn;
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Map, Value};
+
+use super::types::{BaseTypes, Crc, GameObjs, GameObjsTypeField, GameObjsTypeHeader};
+
+lazy_static::lazy_static! {
+    pub static ref SCHEMA: Mutex<Schema> = {
+        const GAMEOBJS_SCHEMA: &str = include_str!("../res/gameobjs_schema.json");
+        Mutex::new(serde_json::from_str(GAMEOBJS_SCHEMA).unwrap())
+    };
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TypeSchema {
+    pub size: u32,
+    pub fields: u32,
+    pub type_fields: Vec<GameObjsTypeField>,
+}
+
+impl TypeSchema {
+    pub fn matches(&self, fields: &[GameObjsTypeField]) -> bool {
+        self.type_fields.len() == fields.len() && zip(&self.type_fields, fields).all(|(a, b)|
+            a.key == b.key && a.kind == b.kind && a.offset == b.offset
+        )
+    }
+}
+
+/// GameObjs type definitions gathered from the vanilla levels, keyed by type name
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Schema {
+    pub types: BTreeMap<Crc, TypeSchema>,
+}
+
+impl Schema {
+    /// Adds the types of a level, types that are already known with a different layout are kept as they are
+    pub fn merge(&mut self, objs: &GameObjs) {
+        for (header, fields) in zip(&objs.types, &objs.type_fields) {
+            match self.types.get(&header.key) {
+                Some(val) if !val.matches(fields) => warn!("Conflicting definitions for type {}", header.key.to_string()),
+                Some(_) => (),
+                None => {
+                    self.types.insert(header.key.clone(), TypeSchema { size: header.size, fields: header.fields, type_fields: fields.clone() });
+                }
+            }
+        }
+    }
+
+    /// Checks the level types against the known definitions and every object against its type,
+    /// types missing from the schema are reported so a schema that was never built rejects every type
+    pub fn validate(&self, objs: &GameObjs) -> Vec<String> {
+        let mut errors = vec![];
+        for (header, fields) in zip(&objs.types, &objs.type_fields) {
+            match self.types.get(&header.key) {
+                Some(val) if !val.matches(fields) => errors.push(format!("Type {} doesn't match the known definition", header.key.to_string())),
+                Some(_) => (),
+                None => errors.push(format!("Unknown type {}", header.key.to_string())),
+            }
+        }
+        for (header, vals) in zip(&objs.obj_headers, &objs.objs) {
+            let Some(i) = objs.type_field_lookup.get(&header.key.key()) else {
+                errors.push(format!("Object of undefined type {}", header.key.to_string()));
+                continue;
+            };
+            let fields = &objs.type_fields[*i];
+            if fields.len() != vals.len() {
+                errors.push(format!("Object of type {} has {} fields, expected {}", header.key.to_string(), vals.len(), fields.len()));
+                continue;
+            }
+            for (field, val) in zip(fields, vals) {
+                if field.kind.key() != val.kind() {
+                    errors.push(format!("Field {} of a {} object should be a {}", field.key.to_string(), header.key.to_string(), field.kind.to_string()));
+                }
+            }
+        }
+        errors
+    }
+
+    /// Adds the definition of a type to the objects if they don't have it yet, returns false if the type is unknown
+    pub fn add_type(&self, objs: &mut GameObjs, kind: &Crc) -> bool {
+        if objs.type_field_lookup.contains_key(&kind.key()) {
+            return true;
+        }
+        let Some(val) = self.types.get(kind) else { return false };
+        objs.add_type(GameObjsTypeHeader { key: kind.clone(), size: val.size, fields: val.fields }, val.type_fields.clone());
+        true
+    }
+
+    /// JSON Schema for the dumped GameObjs files
+    pub fn json_schema(&self) -> Value {
+        let objs = self.types.iter().map(|(name, val)| json!({
+            "type": "object",
+            "properties": {
+                "type": { "const": name.to_string() },
+                "layer": { "type": "integer" },
+                "fields": {
+                    "type": "object",
+                    "properties": val.type_fields.iter().map(|x| (x.key.to_string(), field_schema(x))).collect::<Map<_, _>>(),
+                    "required": val.type_fields.iter().map(|x| x.key.to_string()).collect::<Vec<_>>(),
+                    "additionalProperties": false,
+                }
+            },
+            "required": ["type", "layer", "fields"],
+        })).collect::<Vec<_>>();
+        // without any known types every object is allowed, an empty anyOf would reject them all
+        let items = if objs.is_empty() { json!({}) } else { json!({ "anyOf": objs }) };
+        json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "title": "GameObjs",
+            "type": "object",
+            "properties": {
+                "gamemodemask": mask_schema(),
+                "objs": { "type": "array", "items": items },
+                "types": { "type": "array" },
+            },
+            "required": ["gamemodemask", "objs", "types"],
+        })
+    }
+}
+
+fn numbers(n: usize) -> Value {
+    json!({ "type": "array", "items": { "type": "number" }, "minItems": n, "maxItems": n })
+}
+
+fn integers(n: usize) -> Value {
+    json!({ "type": "array", "items": { "type": "integer" }, "minItems": n, "maxItems": n })
+}
+
+/// Masks are written as numbers, or as lists of gamemode names and bit indices when the names are known
+fn mask_schema() -> Value {
+    json!({ "anyOf": [
+        { "type": "integer" },
+        { "type": "array", "items": { "type": ["string", "integer"] } },
+    ] })
+}
+
+fn field_schema(field: &GameObjsTypeField) -> Value {
+    if field.key.key() == GameObjs::GAMEMODEMASK_FIELD {
+        mask_schema()
+    } else {
+        kind_schema(field.kind.key())
+    }
+}
+
+fn kind_schema(kind: u32) -> Value {
+    match kind {
+        BaseTypes::CRC_KEY | BaseTypes::STRING_KEY => json!({ "type": "string" }),
+        BaseTypes::GUID_KEY | BaseTypes::COLOR_KEY | BaseTypes::INT_KEY | BaseTypes::BYTE_KEY => json!({ "type": "integer" }),
+        BaseTypes::FLOAT_KEY => json!({ "type": "number" }),
+        BaseTypes::BOOL_KEY => json!({ "type": "boolean" }),
+        BaseTypes::VECTOR2_KEY => numbers(2),
+        BaseTypes::VECTOR3_KEY => numbers(3),
+        BaseTypes::VECTOR4_KEY => numbers(4),
+        BaseTypes::MATRIX4X4_KEY => numbers(16),
+        BaseTypes::STRINGLIST_KEY | BaseTypes::CRCLIST_KEY => json!({ "type": "array", "items": { "type": "string" } }),
+        BaseTypes::OBJECTLIST_KEY | BaseTypes::INTLISTS_KEY => json!({ "type": "array", "items": { "type": "integer" } }),
+        BaseTypes::NODELIST_KEY => json!({ "type": "array", "items": integers(4) }),
+        BaseTypes::WEIGHTLIST_KEY => json!({ "type": "array", "items": integers(5) }),
+        BaseTypes::MATRIXLIST_KEY => json!({ "type": "array", "items": numbers(16) }),
+        _ => json!({}),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::types::GameObjsObjHeader;
+    use super::*;
+
+    fn field(name: &str, kind: &str, offset: u32) -> GameObjsTypeField {
+        GameObjsTypeField { key: name.into(), kind: kind.into(), offset }
+    }
+
+    fn level() -> GameObjs {
+        let types = vec![GameObjsTypeHeader { key: "Spawn".into(), size: 2, fields: 2 }];
+        let type_fields = vec![vec![field("GUID", "GUID", 0), field("Health", "Float", 4)]];
+        GameObjs {
+            type_field_lookup: HashMap::from([(Crc::from("Spawn").key(), 0)]),
+            obj_headers: vec![GameObjsObjHeader { key: "Spawn".into(), ..Default::default() }],
+            objs: vec![vec![BaseTypes::GUID(1), BaseTypes::Float(250.0)]],
+            types,
+            type_fields,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn shipped_schema() {
+        let shipped: Schema = serde_json::from_str(include_str!("../res/gameobjs_schema.json")).unwrap();
+        let errors = shipped.validate(&level());
+        // every type of the level has to be in the schema, so until it is built from the vanilla levels it rejects them
+        assert_eq!(errors.is_empty(), shipped.types.contains_key(&"Spawn".into()), "{:?}", errors);
+    }
+
+    #[test]
+    fn validate_level() {
+        let mut schema = Schema::default();
+        schema.merge(&level());
+        let schema: Schema = serde_json::from_slice(&serde_json::to_vec(&schema).unwrap()).unwrap();
+        assert_eq!(schema.validate(&level()), Vec::<String>::new());
+
+        let mut objs = level();
+        objs.objs[0][1] = BaseTypes::Int(250);
+        assert_eq!(schema.validate(&objs).len(), 1);
+
+        let mut objs = level();
+        objs.type_fields[0][1].offset = 8;
+        assert_eq!(schema.validate(&objs).len(), 1);
+
+        let mut objs = GameObjs::default();
+        assert!(schema.add_type(&mut objs, &"Spawn".into()));
+        assert!(!schema.add_type(&mut objs, &"Missing".into()));
+        assert_eq!(objs.fields_of(&"Spawn".into()).len(), 2);
+    }
+}
@@ -0,0 +1,125 @@
+use std::{collections::{BTreeMap, HashMap, HashSet}, fs, path::Path};
+use itertools::Itertools;
+use serde::Serialize;
+
+use super::{
+    audio::AudioTable,
+    bnk::{Bank, HircObj},
+    guids,
+    types::{BaseTypes, Crc, GameObjs},
+};
+
+const LEVEL_BANKS: &str = "LevelSpecificBanks";
+const MODE_BANKS: &str = "ModeSpecificBanks";
+
+/// Bank names are compared without case or the .bnk extension. New banks are added with the extension like
+/// examples/full_character_move.py does, this hasn't been checked against the names in the vanilla levels
+fn bank_id(name: &str) -> String {
+    let name = name.to_lowercase();
+    name.strip_suffix(".bnk").map(|x| x.to_string()).unwrap_or(name)
+}
+
+/// Every soundbank in a folder, by file name
+pub fn load_banks<P: AsRef<Path>>(path: P) -> Vec<(String, Bank)> {
+    fs::read_dir(path).unwrap().map(|x| x.unwrap().path())
+        .filter(|x| x.extension().is_some_and(|x| x.eq_ignore_ascii_case("bnk")))
+        .sorted()
+        .map(|x| (x.file_name().unwrap().to_str().unwrap().to_string(), Bank::parse(&x)))
+        .collect()
+}
+
+/// The banks holding each event, by wwise id
+pub fn event_banks(banks: &[(String, Bank)]) -> HashMap<u32, Vec<String>> {
+    let mut found: HashMap<u32, Vec<String>> = HashMap::new();
+    for (name, bank) in banks {
+        for obj in bank.objects().iter().filter(|x| x.kind == HircObj::EVENT) {
+            found.entry(obj.id).or_default().push(name.clone());
+        }
+    }
+    found
+}
+
+/// Events of the audio table named by the crc and string fields of an object and everything it references
+pub fn object_events(objs: &GameObjs, table: &AudioTable, guid: u32) -> Vec<Crc> {
+    let events = table.events.iter().map(|x| x.key.clone()).collect::<HashSet<_>>();
+    let mut keys = vec![];
+    for i in guids::object_tree(objs, guid) {
+        for field in &objs.objs[i] {
+            match field {
+                BaseTypes::CRC(val) => keys.push(val.clone()),
+                BaseTypes::CRCList(vals) => keys.extend(vals.iter().cloned()),
+                BaseTypes::String(val) if !val.is_empty() => keys.push(Crc::from_string(val)),
+                BaseTypes::StringList(vals) => keys.extend(vals.iter().map(|x| Crc::from_string(x))),
+                _ => ()
+            }
+        }
+    }
+    keys.into_iter().filter(|x| events.contains(x)).unique().collect()
+}
+
+fn bank_list<'a>(objs: &'a GameObjs, obj: usize, name: &str) -> &'a [String] {
+    let name = Crc::from_string(name);
+    objs.fields_of(&objs.obj_headers[obj].key).iter().position(|x| x.key == name)
+        .and_then(|i| match &objs.objs[obj][i] {
+            BaseTypes::StringList(vals) => Some(&vals[..]),
+            _ => None,
+        }).unwrap_or_default()
+}
+
+/// Banks that are always loaded in a level, and those of a gamemode if one is given, see bank_id
+pub fn loaded_banks(objs: &GameObjs, gamemode: Option<u32>) -> HashSet<String> {
+    let mut banks = (0..objs.objs.len()).flat_map(|i| bank_list(objs, i, LEVEL_BANKS)).map(|x| bank_id(x)).collect::<HashSet<_>>();
+    if let Some(obj) = gamemode.and_then(|guid| objs.find(guid)) {
+        banks.extend(bank_list(objs, obj, MODE_BANKS).iter().map(|x| bank_id(x)));
+    }
+    banks
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    /// Every bank each event is in
+    pub events: BTreeMap<String, Vec<String>>,
+    /// Events that aren't in any of the banks
+    pub missing: Vec<String>,
+    /// Banks that need loading for events that none of the loaded banks have
+    pub required: Vec<String>,
+}
+
+pub fn report(table: &AudioTable, events: &[Crc], found: &HashMap<u32, Vec<String>>, loaded: &HashSet<String>) -> Report {
+    let ids = table.events.iter().map(|x| (x.key.clone(), x.id)).collect::<HashMap<_, _>>();
+    let mut val = Report::default();
+    let mut needed = vec![];
+    for event in events {
+        let banks = ids.get(event).and_then(|x| found.get(x)).cloned().unwrap_or_default();
+        if banks.is_empty() {
+            val.missing.push(event.to_string());
+        } else if !banks.iter().any(|x| loaded.contains(&bank_id(x))) {
+            needed.push(banks.clone());
+        }
+        val.events.insert(event.to_string(), banks);
+    }
+    // take the bank with the most uncovered events until every event is covered
+    while !needed.is_empty() {
+        let bank = needed.iter().flatten().counts().into_iter().max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0))).unwrap().0.clone();
+        needed.retain(|x| !x.contains(&bank));
+        val.required.push(bank);
+    }
+    val.required.sort();
+    val
+}
+
+/// Adds banks to the ModeSpecificBanks of a gamemode object, returns how many were new
+pub fn add_banks(objs: &mut GameObjs, gamemode: u32, banks: &[String]) -> Result<usize, String> {
+    let name = Crc::from_string(MODE_BANKS);
+    let mut vals = match objs.get_field(gamemode, &name) {
+        Some(BaseTypes::StringList(vals)) => vals.clone(),
+        Some(_) => return Err(format!("{} of object {} isn't a StringList", MODE_BANKS, gamemode)),
+        None => return Err(format!("Object {} has no {} field", gamemode, MODE_BANKS)),
+    };
+    let n = vals.len();
+    let known = vals.iter().map(|x| bank_id(x)).collect::<HashSet<_>>();
+    vals.extend(banks.iter().filter(|x| !known.contains(&bank_id(x))).cloned());
+    let added = vals.len() - n;
+    objs.set_field(gamemode, &name, BaseTypes::StringList(vals));
+    Ok(added)
+}
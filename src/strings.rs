@@ -0,0 +1,166 @@
+use std::{collections::{BTreeMap, HashMap, HashSet}, fs, path::Path};
+use itertools::Itertools;
+use serde_json::{to_value, Value};
+
+use super::{
+    level_alt::Level,
+    types::{self, hash_string, BaseTypes, Crc, GameObjs, SubBlock, STRING_LOOKUP},
+};
+
+/// Json views of the parts of a level that hold crcs, named like the dumped files
+fn sources(level: &Level) -> Vec<(String, Value)> {
+    let mut vals = vec![
+        ("objas".to_string(), to_value(&level.objas).unwrap()),
+        ("obj0s".to_string(), to_value(&level.obj0s).unwrap()),
+        ("pak_vals_a".to_string(), to_value(&level.pak_vals_a).unwrap()),
+        ("animation_block_infos".to_string(), to_value(&level.animation_block_infos).unwrap()),
+        ("pfield_infos".to_string(), to_value(&level.pfield_infos).unwrap()),
+        ("string_keys".to_string(), to_value(&level.string_keys).unwrap()),
+    ];
+    vals.extend(level.meshes.iter().map(|(k, v)| (format!("meshes/{}", k.to_string()), to_value(v).unwrap())));
+    vals.extend(level.textures.iter().map(|(k, v)| (format!("textures/{}", k.to_string()), to_value(v.info()).unwrap())));
+    vals.extend(level.animations.iter().map(|(k, v)| (format!("animations/{}", k.to_string()), to_value(v).unwrap())));
+    vals.extend(level.effects.iter().map(|(k, v)| (format!("effects/{}", k.to_string()), v.to_json(&level.gamemodes))));
+    vals.extend(level.foliages.iter().map(|(k, v)| (format!("foliage/{}", k.to_string()), to_value(v.iter().map(|(x, _)| x).collect::<Vec<_>>()).unwrap())));
+    vals.extend(level.radiosity.keys().map(|k| (format!("radiosity/{}", k.to_string()), Value::Null)));
+    vals.extend(level.gfx_blocks.keys().map(|k| (format!("gfxs/{}", k.to_string()), Value::Null)));
+    for (header, block) in level.sub_blocks1.block_headers.iter().zip(&level.sub_blocks1.blocks) {
+        if let SubBlock::GameObjs(objs) = block {
+            vals.push((format!("sub_blocks1/{}", header.key.to_string()), objs.to_json(&level.gamemodes)));
+        }
+    }
+    vals
+}
+
+fn unresolved_key(val: &str) -> Option<u32> {
+    let hex = val.strip_prefix("0x")?;
+    (hex.len() == 8).then(|| u32::from_str_radix(hex, 16).ok()).flatten()
+}
+
+/// Calls visit with every string and object key, along with its path
+fn walk(val: &Value, path: &str, visit: &mut impl FnMut(&str, &str)) {
+    match val {
+        Value::String(val) => visit(val, path),
+        Value::Array(vals) => vals.iter().enumerate().for_each(|(i, x)| walk(x, &format!("{}[{}]", path, i), visit)),
+        Value::Object(vals) => for (k, v) in vals {
+            let path = format!("{}.{}", path, k);
+            visit(k, &path);
+            walk(v, &path, visit);
+        },
+        _ => ()
+    }
+}
+
+/// Every crc that has no known string, with the places it's used
+pub fn unresolved(level: &Level) -> BTreeMap<u32, Vec<String>> {
+    let mut found: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+    let mut visit = |val: &str, path: &str| if let Some(key) = unresolved_key(val) {
+        found.entry(key).or_default().push(path.to_string());
+    };
+    for (name, val) in sources(level) {
+        visit(name.rsplit('/').next().unwrap(), &name);
+        walk(&val, &name, &mut visit);
+    }
+    found
+}
+
+/// Crcs of an objects block: the type and field names and the values of crc fields
+pub fn objs_crcs(objs: &GameObjs) -> impl Iterator<Item = &Crc> {
+    objs.types.iter().map(|x| &x.key)
+        .chain(objs.type_fields.iter().flatten().flat_map(|x| [&x.key, &x.kind]))
+        .chain(objs.obj_headers.iter().map(|x| &x.key))
+        .chain(objs.objs.iter().flatten().flat_map(|x| match x {
+            BaseTypes::CRC(val) => std::slice::from_ref(val),
+            BaseTypes::CRCList(vals) => &vals[..],
+            _ => &[],
+        }))
+}
+
+/// Number of distinct crcs that have a string and that don't, not counting the empty 0 crc
+pub fn counts<'a>(crcs: impl IntoIterator<Item = &'a Crc>) -> (usize, usize) {
+    let (mut resolved, mut unresolved) = (HashSet::new(), HashSet::new());
+    for crc in crcs {
+        match crc {
+            _ if crc.key() == 0 => (),
+            Crc::Str(_) => { resolved.insert(crc.key()); },
+            Crc::Key(key) => { unresolved.insert(*key); },
+        }
+    }
+    (resolved.len(), unresolved.len())
+}
+
+/// Adds the strings of a dictionary file, one per line, to the lookup
+pub fn load_dict<P: AsRef<Path>>(path: P) -> usize {
+    let data = fs::read_to_string(path).unwrap();
+    let vals = data.lines().map(|x| x.trim_end_matches('\r')).filter(|x| !x.is_empty()).map(String::from).collect::<Vec<_>>();
+    types::update_strings(&vals);
+    vals.len()
+}
+
+/// Writes every known string, sorted, in the same format as the bundled dictionary
+pub fn export_dict<P: AsRef<Path>>(path: P) -> usize {
+    let vals = STRING_LOOKUP.lock().unwrap().values().filter(|x| !x.is_empty()).cloned().sorted().dedup().collect::<Vec<_>>();
+    fs::write(path, vals.join("\n")).unwrap();
+    vals.len()
+}
+
+/// A crc given as 0x hex or as a number
+pub fn parse_key(val: &str) -> Option<u32> {
+    val.strip_prefix("0x").and_then(|x| u32::from_str_radix(x, 16).ok()).or_else(|| val.parse().ok())
+}
+
+/// Looks up the strings of crcs, also finding the `*` secondary texture keys from the string they are masked by
+pub struct Unhasher {
+    lookup: HashMap<u32, String>,
+    masked: HashMap<u32, String>,
+}
+
+impl Unhasher {
+    pub fn new() -> Self {
+        let lookup = STRING_LOOKUP.lock().unwrap().clone();
+        let masked = lookup.iter().map(|(k, v)| (hash_string("*".as_bytes(), Some(*k)), v.clone())).collect();
+        Self { lookup, masked }
+    }
+
+    pub fn get(&self, key: u32) -> Option<String> {
+        self.lookup.get(&key).cloned().or_else(|| self.masked.get(&key).map(|x| format!("* (masked by {})", x)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_unhash() {
+        types::update_strings(&["lotrc_test_string".to_string()]);
+        let key = hash_string("lotrc_test_string".as_bytes(), None);
+        let unhasher = Unhasher::new();
+        assert_eq!(unhasher.get(key).as_deref(), Some("lotrc_test_string"));
+        assert_eq!(unhasher.get(hash_string("*".as_bytes(), Some(key))).as_deref(), Some("* (masked by lotrc_test_string)"));
+        assert_eq!(Crc::from_string(&Crc::Key(key).to_string()).key(), key);
+        assert_eq!(Crc::from_string("lotrc_test_string").key(), key);
+    }
+
+    #[test]
+    fn hash_chaining() {
+        let key = hash_string("Level".as_bytes(), None);
+        assert_eq!(!types::hash_update(types::hash_update(!0, b"Le"), b"vel"), key);
+        assert_eq!(hash_string("vel".as_bytes(), Some(hash_string("Le".as_bytes(), None))), key);
+        assert_ne!(hash_string("Level".as_bytes(), Some(1)), key);
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(parse_key("0x0000ABCD"), Some(0xABCD));
+        assert_eq!(parse_key("1234"), Some(1234));
+        assert_eq!(parse_key("0xZZ"), None);
+        assert_eq!(parse_key("name"), None);
+    }
+
+    #[test]
+    fn crc_counts() {
+        let crcs = [Crc::from_string("Level"), Crc::from_string("level"), Crc::Key(0x1234), Crc::Key(0x1234), Crc::Key(0)];
+        assert_eq!(counts(&crcs), (1, 1));
+    }
+}
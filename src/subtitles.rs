@@ -0,0 +1,204 @@
+use itertools::Itertools;
+
+use super::types::{Crc, SSA, SSAVal, SubBlock, SubBlocks};
+
+/// Names of the subtitle blocks
+pub fn list(blocks: &[&SubBlocks]) -> Vec<Crc> {
+    blocks.iter().flat_map(|x| x.block_headers.iter().zip(&x.blocks))
+        .filter(|(_, block)| matches!(block, SubBlock::SSA(_)))
+        .map(|(header, _)| header.key.clone()).collect()
+}
+
+pub fn get<'a>(blocks: &[&'a SubBlocks], name: &Crc) -> Option<&'a SSA> {
+    blocks.iter().flat_map(|x| x.block_headers.iter().zip(&x.blocks))
+        .find_map(|(header, block)| match block {
+            SubBlock::SSA(val) if header.key == *name => Some(val),
+            _ => None,
+        })
+}
+
+pub fn get_mut<'a>(blocks: &'a mut [&mut SubBlocks], name: &Crc) -> Option<&'a mut SSA> {
+    blocks.iter_mut().flat_map(|x| x.block_headers.iter().zip(x.blocks.iter_mut()))
+        .find_map(|(header, block)| match block {
+            SubBlock::SSA(val) if header.key == *name => Some(val),
+            _ => None,
+        })
+}
+
+/// `hours` is the least number of digits the hours are padded to
+fn format_time(t: f32, hours: usize, sep: char, digits: usize) -> String {
+    let scale = 10u64.pow(digits as u32);
+    let t = (t.max(0.0) as f64 * scale as f64).round() as u64;
+    let (s, frac) = (t / scale, t % scale);
+    format!("{:0hours$}:{:02}:{:02}{}{:0digits$}", s / 3600, s / 60 % 60, s % 60, sep, frac)
+}
+
+fn parse_time(val: &str) -> Result<f64, String> {
+    let parts = val.trim().split(':').collect::<Vec<_>>();
+    let [h, m, s] = parts[..] else { return Err(format!("Invalid time {}", val)) };
+    let err = |_| format!("Invalid time {}", val);
+    Ok(h.parse::<f64>().map_err(err)? * 3600.0 + m.parse::<f64>().map_err(err)? * 60.0 + s.replace(',', ".").parse::<f64>().map_err(err)?)
+}
+
+/// Keeps the original time if the file still shows it, so unedited times are unchanged
+fn time(parsed: f64, orig: f32, digits: usize) -> f32 {
+    let scale = 10f64.powi(digits as i32);
+    if (orig as f64 * scale).round() == (parsed * scale).round() { orig } else { parsed as f32 }
+}
+
+pub fn to_srt(ssa: &SSA) -> String {
+    ssa.vals.iter().zip(&ssa.strings).enumerate().map(|(i, (val, text))| format!(
+        "{}\r\n{} --> {}\r\n{}\r\n", i + 1, format_time(val.t_start, 2, ',', 3), format_time(val.t_end, 2, ',', 3), text.replace('\n', "\r\n")
+    )).join("\r\n")
+}
+
+/// An entry starts at an index line followed by a times line, so its text can hold blank lines
+fn srt_entry_start(lines: &[&str], i: usize) -> bool {
+    let index = lines[i].trim();
+    !index.is_empty() && index.chars().all(|x| x.is_ascii_digit()) && lines.get(i + 1).is_some_and(|x| x.contains("-->"))
+}
+
+/// Entries take the values srt can't hold from the entry of the block at the same position
+pub fn from_srt(data: &str, orig: &SSA) -> Result<SSA, String> {
+    let data = data.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    let lines = data.lines().collect::<Vec<_>>();
+    let mut ssa = SSA::default();
+    let mut i = 0;
+    while i < lines.len() {
+        if !srt_entry_start(&lines, i) {
+            if !lines[i].trim().is_empty() {
+                return Err(format!("Expected an index and times, got {}", lines[i]));
+            }
+            i += 1;
+            continue;
+        }
+        let times = lines[i + 1];
+        let (start, end) = times.split_once("-->").ok_or_else(|| format!("Invalid times {}", times))?;
+        let base = orig.vals.get(ssa.vals.len()).cloned().unwrap_or_default();
+        ssa.vals.push(SSAVal {
+            t_start: time(parse_time(start)?, base.t_start, 3),
+            t_end: time(parse_time(end)?, base.t_end, 3),
+            ..base
+        });
+        i += 2;
+        let text_start = i;
+        while i < lines.len() && !srt_entry_start(&lines, i) {
+            i += 1;
+        }
+        // the blank lines before the next entry separate it
+        ssa.strings.push(lines[text_start..i].join("\n").trim_end_matches('\n').to_string());
+    }
+    Ok(ssa)
+}
+
+/// The unknown values of each entry are kept in the Effect field
+pub fn to_ass(ssa: &SSA, title: &str) -> String {
+    let mut data = format!(
+        "[Script Info]\r\nTitle: {}\r\nScriptType: v4.00+\r\n\r\n\
+        [V4+ Styles]\r\n\
+        Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\r\n\
+        Style: Default,Arial,20,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1\r\n\r\n\
+        [Events]\r\n\
+        Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\r\n", title);
+    for (val, text) in ssa.vals.iter().zip(&ssa.strings) {
+        data.push_str(&format!(
+            "Dialogue: 0,{},{},Default,,0,0,0,{} {},{}\r\n",
+            format_time(val.t_start, 1, '.', 2), format_time(val.t_end, 1, '.', 2), val.unk_2, val.unk_3, text.replace('\n', "\\N")
+        ));
+    }
+    data
+}
+
+pub fn from_ass(data: &str, orig: &SSA) -> Result<SSA, String> {
+    let mut ssa = SSA::default();
+    for line in data.lines().filter_map(|x| x.strip_prefix("Dialogue:")) {
+        let fields = line.splitn(10, ',').collect::<Vec<_>>();
+        let [_, start, end, _, _, _, _, _, effect, text] = fields[..] else { return Err(format!("Invalid dialogue line {}", line)) };
+        let base = orig.vals.get(ssa.vals.len()).cloned().unwrap_or_default();
+        let mut unks = effect.split_whitespace().map(|x| x.parse::<u32>().ok());
+        ssa.vals.push(SSAVal {
+            t_start: time(parse_time(start)?, base.t_start, 2),
+            t_end: time(parse_time(end)?, base.t_end, 2),
+            unk_2: unks.next().flatten().unwrap_or(base.unk_2),
+            unk_3: unks.next().flatten().unwrap_or(base.unk_3),
+            off: 0,
+        });
+        ssa.strings.push(text.replace("\\N", "\n").replace("\\n", "\n"));
+    }
+    Ok(ssa)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ssa() -> SSA {
+        SSA {
+            vals: vec![
+                SSAVal { t_start: 1.2346, t_end: 3.5, unk_2: 7, unk_3: 9, off: 0 },
+                SSAVal { t_start: 3725.1, t_end: 3727.3333, unk_2: 1, unk_3: 0, off: 0 },
+            ],
+            strings: vec!["Hello".to_string(), "Two\nlines".to_string()],
+        }
+    }
+
+    fn check(val: &SSA, orig: &SSA) {
+        assert_eq!(val.strings, orig.strings);
+        assert_eq!(val.vals.len(), orig.vals.len());
+        for (a, b) in val.vals.iter().zip(&orig.vals) {
+            assert_eq!((a.t_start, a.t_end, a.unk_2, a.unk_3), (b.t_start, b.t_end, b.unk_2, b.unk_3));
+        }
+    }
+
+    #[test]
+    fn srt_round_trip() {
+        let orig = ssa();
+        let data = to_srt(&orig);
+        assert!(data.contains("01:02:05,100 --> 01:02:07,333"));
+        check(&from_srt(&data, &orig).unwrap(), &orig);
+    }
+
+    #[test]
+    fn ass_round_trip() {
+        let orig = ssa();
+        let data = to_ass(&orig, "test");
+        assert!(data.contains("Dialogue: 0,1:02:05.10,1:02:07.33,Default,,0,0,0,1 0,Two\\Nlines"));
+        check(&from_ass(&data, &orig).unwrap(), &orig);
+        // the unknown values are read back from the file
+        let val = from_ass(&data, &SSA::default()).unwrap();
+        assert_eq!((val.vals[0].unk_2, val.vals[0].unk_3), (7, 9));
+    }
+
+    #[test]
+    fn srt_edits() {
+        let orig = ssa();
+        let data = to_srt(&orig).replace("00:00:03,500", "00:00:04,250").replace("Hello", "Bye");
+        let val = from_srt(&data, &orig).unwrap();
+        assert_eq!(val.vals[0].t_end, 4.25);
+        assert_eq!(val.vals[0].t_start, orig.vals[0].t_start);
+        assert_eq!(val.strings[0], "Bye");
+        assert!(from_srt("1\nnot a time\ntext", &orig).is_err());
+        assert!(from_srt("1\n00:00:01,000 --> soon\ntext", &orig).is_err());
+    }
+
+    #[test]
+    fn srt_blank_lines() {
+        let data = "\u{feff}1\n00:00:01,000 --> 00:00:02,000\nFirst\n\nstill first\n\n\n\
+            2\n00:00:03,000 --> 00:00:04,000\n12\n3\n00:00:05,000 --> 00:00:06,000\nLast";
+        let val = from_srt(data, &SSA::default()).unwrap();
+        assert_eq!(val.strings, ["First\n\nstill first", "12", "Last"]);
+        assert_eq!(val.vals.iter().map(|x| (x.t_start, x.t_end)).collect::<Vec<_>>(), [(1.0, 2.0), (3.0, 4.0), (5.0, 6.0)]);
+    }
+
+    #[test]
+    fn long_times() {
+        let orig = SSA {
+            vals: vec![SSAVal { t_start: 36005.5, t_end: 360000.25, unk_2: 0, unk_3: 0, off: 0 }],
+            strings: vec!["Late".to_string()],
+        };
+        assert!(to_ass(&orig, "test").contains("Dialogue: 0,10:00:05.50,100:00:00.25,"));
+        assert!(to_srt(&orig).contains("10:00:05,500 --> 100:00:00,250"));
+        check(&from_ass(&to_ass(&orig, "test"), &orig).unwrap(), &orig);
+        check(&from_srt(&to_srt(&orig), &orig).unwrap(), &orig);
+    }
+}
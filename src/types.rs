@@ -1,6 +1,6 @@
-use std::{any::TypeId, collections::HashMap, iter::zip, mem::size_of};
+use std::{any::TypeId, cell::RefCell, collections::{HashMap, HashSet}, iter::zip, mem::size_of};
 use log::warn;
-use serde_json::{Value, json, to_vec_pretty, Map};
+use serde_json::{Value, json, Map};
 use zerocopy::{AsBytes, ByteOrder, FromBytes, BE, F32, LE, U16, U32, U64, I32};
 use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
@@ -9,7 +9,7 @@ use std::sync::Mutex;
 use std::io::prelude::*;
 
 use super::lua_stuff::LuaCompiler;
-use super::read_write::{Reader, Writer, PathStuff};
+use super::read_write::{Format, Reader, Writer, PathStuff};
 
 use lotrc_rs_proc::OrderedData;
 pub trait OrderedData where Self: Sized + Clone + Default {
@@ -149,17 +149,22 @@ const INDEX_ARRAY: [usize; 256] = [
 ];
 
 pub const fn hash_string(string: &[u8], mask: Option<u32>) -> u32 {
-    let mut h = !match mask {
+    let h = !match mask {
         Some(val) => val,
         None => 0,
     };
+    !hash_update(h, string)
+}
+
+/// Feeds more bytes into an unfinished hash, the final hash is the inverse of the state
+pub const fn hash_update(mut h: u32, string: &[u8]) -> u32 {
     let mut i: usize = 0;
     loop {
         if i >= string.len() { break; }
         h = (h << 8) ^ HASHING_ARRAY[INDEX_ARRAY[string[i] as usize] ^ (h >> 24) as usize];
         i += 1;
     }
-    !h
+    h
 }
 
 lazy_static::lazy_static! {
@@ -180,14 +185,100 @@ lazy_static::lazy_static! {
 
     pub static ref ZIP: Mutex<bool> = Mutex::new(true);
 
+    /// Format the values of dumps are written in
+    pub static ref FORMAT: Mutex<Format> = Mutex::new(Format::Json);
 }
 
 pub fn update_strings(vals: &[String]) {
     STRING_LOOKUP.lock().unwrap().extend(vals.iter().map(|x| (hash_string(x.as_bytes(), None), x.clone())));
 }
 
+pub trait Mask: Copy + Serialize {
+    fn bits(self) -> u32;
+    fn from_bits(val: u32) -> Self;
+}
+
+impl Mask for i32 {
+    fn bits(self) -> u32 { self as u32 }
+    fn from_bits(val: u32) -> Self { val as i32 }
+}
+
+impl Mask for u32 {
+    fn bits(self) -> u32 { self }
+    fn from_bits(val: u32) -> Self { val }
+}
+
+/// A gamemode mask as the list of gamemode names, bits without a name are given by index.
+/// Masks stay plain numbers when no names are given
+pub fn mask_to_json<T: Mask>(mask: T, names: &[Crc]) -> Value {
+    if names.is_empty() {
+        return json!(mask);
+    }
+    let bits = mask.bits();
+    Value::Array((0..32).filter(|i| bits & (1 << i) != 0).map(|i| match names.get(i) {
+        Some(name) => json!(name.to_string()),
+        None => json!(i),
+    }).collect())
+}
+
+/// Reads back a mask written by mask_to_json, plain numbers are always accepted
+pub fn mask_from_json<T: Mask>(val: &Value, names: &[Crc]) -> Result<T, String> {
+    match val {
+        Value::Array(vals) => {
+            let mut mask = 0u32;
+            for val in vals {
+                let bit = match val {
+                    Value::String(name) => {
+                        let key = Crc::from_string(name);
+                        names.iter().position(|x| *x == key).ok_or_else(|| format!("Unknown gamemode {}", name))?
+                    },
+                    val => val.as_u64().ok_or_else(|| format!("Invalid gamemode {}", val))? as usize,
+                };
+                if bit >= 32 {
+                    return Err(format!("Gamemode {} doesn't fit in a 32 bit mask", bit));
+                }
+                mask |= 1 << bit;
+            }
+            Ok(T::from_bits(mask))
+        },
+        val => val.as_i64().map(|x| T::from_bits(x as u32)).ok_or_else(|| format!("Invalid gamemode mask {}", val)),
+    }
+}
+
+thread_local! {
+    static MASK_NAMES: RefCell<Vec<Crc>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Runs `f` with the `gamemode_mask` fields read and written on this thread using the given gamemode names.
+/// serde can't be handed the names, so they are only set for the length of the call
+pub fn with_gamemodes<T>(names: &[Crc], f: impl FnOnce() -> T) -> T {
+    struct Restore(Vec<Crc>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            MASK_NAMES.with(|x| *x.borrow_mut() = std::mem::take(&mut self.0));
+        }
+    }
+    let _restore = Restore(MASK_NAMES.with(|x| x.replace(names.to_vec())));
+    f()
+}
+
+/// serde helper for gamemode mask fields, see `with_gamemodes`
+pub mod gamemode_mask {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use serde_json::Value;
+
+    pub fn serialize<T: super::Mask, S: Serializer>(val: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        super::MASK_NAMES.with(|names| super::mask_to_json(*val, &names.borrow())).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T: super::Mask, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+        let val = Value::deserialize(deserializer)?;
+        super::MASK_NAMES.with(|names| super::mask_from_json(&val, &names.borrow())).map_err(serde::de::Error::custom)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(from = "&str", into = "String")]
+#[serde(from = "String", into = "String")]
 pub enum Crc {
     Str(Box<str>),
     Key(u32)
@@ -282,6 +373,12 @@ impl From<&str> for Crc {
     }
 }
 
+impl From<String> for Crc {
+    fn from(value: String) -> Self {
+        Crc::from_string(&value)
+    }
+}
+
 impl From<Crc> for String {
     fn from(value: Crc) -> Self {
         value.to_string()
@@ -334,11 +431,11 @@ impl Strings {
     }
 
     pub fn to_file(&self, writer: Writer) {
-        writer.with_extension("json").write(&to_vec_pretty(&json!(self.strings)).unwrap());
+        writer.write_val(&self.strings);
     }
 
     pub fn from_file(reader: Reader) -> Self {
-        let vals = serde_json::from_slice::<Value>(&reader.with_extension("json").read()).unwrap();
+        let vals = reader.read_val::<Value>();
         let strings = vals.as_array().unwrap().iter().map(|x| x.as_str().unwrap().to_string()).collect::<Vec<_>>();
         Self { strings }
     }
@@ -406,6 +503,74 @@ pub struct Matrix4x4 {
     pub w: Vector4,
 }
 
+impl Matrix4x4 {
+    pub fn to_array(&self) -> [[f32; 4]; 4] {
+        [&self.x, &self.y, &self.z, &self.w].map(|v| [v.x, v.y, v.z, v.w])
+    }
+
+    pub fn from_array(vals: [[f32; 4]; 4]) -> Self {
+        let [x, y, z, w] = vals.map(|[x, y, z, w]| Vector4 { x, y, z, w });
+        Self { x, y, z, w }
+    }
+
+    /// Inverse through the cofactor expansion, None if the matrix is singular
+    pub fn inverse(&self) -> Option<Self> {
+        let m = self.to_array();
+        let mut cof = [[0f32; 4]; 4];
+        for (i, row) in cof.iter_mut().enumerate() {
+            for (j, val) in row.iter_mut().enumerate() {
+                let minor = (0..4).filter(|r| *r != i).map(|r|
+                    (0..4).filter(|c| *c != j).map(|c| m[r][c]).collect::<Vec<_>>()
+                ).collect::<Vec<_>>();
+                let det3 = minor[0][0] * (minor[1][1] * minor[2][2] - minor[1][2] * minor[2][1])
+                    - minor[0][1] * (minor[1][0] * minor[2][2] - minor[1][2] * minor[2][0])
+                    + minor[0][2] * (minor[1][0] * minor[2][1] - minor[1][1] * minor[2][0]);
+                *val = if (i + j) % 2 == 0 { det3 } else { -det3 };
+            }
+        }
+        let det: f32 = (0..4).map(|j| m[0][j] * cof[0][j]).sum();
+        // only exactly singular matrices fail, bone matrices can be small enough for any fixed epsilon
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        let mut inv = [[0f32; 4]; 4];
+        for (i, row) in inv.iter_mut().enumerate() {
+            for (j, val) in row.iter_mut().enumerate() {
+                *val = cof[j][i] / det;
+            }
+        }
+        Some(Self::from_array(inv))
+    }
+
+    pub fn transpose(&self) -> Self {
+        let m = self.to_array();
+        Self::from_array(std::array::from_fn(|i| std::array::from_fn(|j| m[j][i])))
+    }
+
+    /// Product for row vectors, `self` is applied first
+    pub fn mul(&self, other: &Self) -> Self {
+        let (a, b) = (self.to_array(), other.to_array());
+        Self::from_array(std::array::from_fn(|i| std::array::from_fn(|j| (0..4).map(|k| a[i][k] * b[k][j]).sum())))
+    }
+
+    /// Row vector matrix of a hkQsTransform (translation, rotation quaternion, scale)
+    pub fn from_qs_transform(t: &Vector4, q: &Vector4, s: &Vector4) -> Self {
+        let (x, y, z, w) = (q.x, q.y, q.z, q.w);
+        Self::from_array([
+            [(1.0 - 2.0 * (y * y + z * z)) * s.x, 2.0 * (x * y + z * w) * s.x, 2.0 * (x * z - y * w) * s.x, 0.0],
+            [2.0 * (x * y - z * w) * s.y, (1.0 - 2.0 * (x * x + z * z)) * s.y, 2.0 * (y * z + x * w) * s.y, 0.0],
+            [2.0 * (x * z + y * w) * s.z, 2.0 * (y * z - x * w) * s.z, (1.0 - 2.0 * (x * x + y * y)) * s.z, 0.0],
+            [t.x, t.y, t.z, 1.0],
+        ])
+    }
+
+    /// Compares every element with a tolerance relative to the element's magnitude
+    pub fn approx_eq(&self, other: &Self, tolerance: f32) -> bool {
+        self.to_array().iter().flatten().zip(other.to_array().iter().flatten())
+            .all(|(a, b)| (a - b).abs() <= tolerance * a.abs().max(b.abs()).max(1.0))
+    }
+}
+
 #[derive(Debug, Default, Clone, OrderedData, Serialize, Deserialize)]
 pub struct Bool {
     pub val: u8,
@@ -767,6 +932,60 @@ impl BaseTypes {
         }
     }
 
+    pub fn new(kind: u32) -> Self {
+        match kind {
+            Self::CRC_KEY => Self::CRC(Crc::default()),
+            Self::GUID_KEY => Self::GUID(0),
+            Self::COLOR_KEY => Self::Color(0),
+            Self::VECTOR2_KEY => Self::Vector2(Vector2::default()),
+            Self::VECTOR3_KEY => Self::Vector3(Vector3::default()),
+            Self::VECTOR4_KEY => Self::Vector4(Vector4::default()),
+            Self::MATRIX4X4_KEY => Self::Matrix4x4(Matrix4x4 {
+                x: Vector4 { x: 1.0, ..Default::default() },
+                y: Vector4 { y: 1.0, ..Default::default() },
+                z: Vector4 { z: 1.0, ..Default::default() },
+                w: Vector4 { w: 1.0, ..Default::default() },
+            }),
+            Self::FLOAT_KEY => Self::Float(0.0),
+            Self::INT_KEY  => Self::Int(0),
+            Self::BOOL_KEY => Self::Bool(Bool::default()),
+            Self::BYTE_KEY => Self::Byte(0),
+            Self::STRING_KEY => Self::String(String::new()),
+            Self::STRINGLIST_KEY => Self::StringList(vec![]),
+            Self::OBJECTLIST_KEY => Self::ObjectList(vec![]),
+            Self::NODELIST_KEY => Self::NodeList(vec![]),
+            Self::INTLISTS_KEY => Self::IntList(vec![]),
+            Self::CRCLIST_KEY => Self::CRCList(vec![]),
+            Self::WEIGHTLIST_KEY => Self::WeightList(vec![]),
+            Self::MATRIXLIST_KEY => Self::MatrixList(vec![]),
+            _ => panic!("Unkown Type {:?}", kind)
+        }
+    }
+
+    pub fn kind(&self) -> u32 {
+        match self {
+            Self::CRC(..) => Self::CRC_KEY,
+            Self::GUID(..) => Self::GUID_KEY,
+            Self::Color(..) => Self::COLOR_KEY,
+            Self::Vector2(..) => Self::VECTOR2_KEY,
+            Self::Vector3(..) => Self::VECTOR3_KEY,
+            Self::Vector4(..) => Self::VECTOR4_KEY,
+            Self::Matrix4x4(..) => Self::MATRIX4X4_KEY,
+            Self::Float(..) => Self::FLOAT_KEY,
+            Self::Int(..) => Self::INT_KEY,
+            Self::Bool(..) => Self::BOOL_KEY,
+            Self::Byte(..) => Self::BYTE_KEY,
+            Self::String(..) => Self::STRING_KEY,
+            Self::StringList(..) => Self::STRINGLIST_KEY,
+            Self::ObjectList(..) => Self::OBJECTLIST_KEY,
+            Self::NodeList(..) => Self::NODELIST_KEY,
+            Self::IntList(..) => Self::INTLISTS_KEY,
+            Self::CRCList(..) => Self::CRCLIST_KEY,
+            Self::WeightList(..) => Self::WEIGHTLIST_KEY,
+            Self::MatrixList(..) => Self::MATRIXLIST_KEY,
+        }
+    }
+
     pub fn from_json(val: &Value, kind: u32) -> Self {
         match kind {
             Self::CRC_KEY => Self::CRC(Crc::from_string(val.as_str().unwrap())),
@@ -782,7 +1001,7 @@ impl BaseTypes {
             }),
             Self::VECTOR4_KEY => Self::Vector4({
                 let vals = val.as_array().unwrap().into_iter().map(|x| x.as_f64().unwrap() as f32).collect::<Vec<_>>();
-                Vector4 { x: vals[0], y: vals[1], z: vals[2], w: vals[4] }
+                Vector4 { x: vals[0], y: vals[1], z: vals[2], w: vals[3] }
             }),
             Self::MATRIX4X4_KEY => Self::Matrix4x4({
                 let vals = val.as_array().unwrap().into_iter().map(|x| x.as_f64().unwrap() as f32).collect::<Vec<_>>();
@@ -887,28 +1106,41 @@ impl SubBlock {
     }
 
 
-    pub fn to_file(&self, writer: Writer, keys: &StringKeys) {
+    pub fn to_file(&self, writer: Writer, keys: &StringKeys, gamemodes: &[Crc]) {
         match self {
             SubBlock::LangStrings(val) => val.to_file(writer, keys),
             SubBlock::Data(val) => val.to_file(writer),
             SubBlock::Spray(val) => val.to_file(writer),
             SubBlock::Crowd(val) => val.to_file(writer),
-            SubBlock::GameObjs(val) => val.to_file(writer),
+            SubBlock::GameObjs(val) => val.to_file(writer, gamemodes),
             SubBlock::AtlasUV(val) => val.to_file(writer),
             SubBlock::Lua(val) => val.to_file(writer),
             SubBlock::SSA(val) => val.to_file(writer),
         }
     }
 
-    pub fn from_file(reader: Reader, info: &SubBlocksBlockHeader, lua: &LuaCompiler) -> Self {
+    /// Reads a data block of a language that isn't built in as strings, it is kept as data if it doesn't have a string for every key
+    pub fn read_language<O: ByteOrder + 'static>(&mut self, key: &Crc, keys: &StringKeys) {
+        if let SubBlock::Data(data) = self {
+            match LangStrings::detect::<O>(&data.data, keys.vals.len()) {
+                Some(val) => *self = SubBlock::LangStrings(val),
+                None => warn!("Language {} doesn't have a string for each of the {} keys, keeping it as data", key.to_string(), keys.vals.len()),
+            }
+        }
+    }
+
+    /// Blocks named in `languages` are read as strings if they were written as strings
+    pub fn from_file(reader: Reader, info: &SubBlocksBlockHeader, keys: &StringKeys, lua: &LuaCompiler, gamemodes: &[Crc], languages: &[Crc]) -> Self {
         match info.key.key() {
             LangStrings::KEY_POLISH | LangStrings::KEY_GERMAN | LangStrings::KEY_FRENCH | LangStrings::KEY_SPANISH | LangStrings::KEY_RUSSIAN | LangStrings::KEY_SWEDISH | LangStrings::KEY_ENGLISH | LangStrings::KEY_ITALIAN | LangStrings::KEY_NORWEGIAN => 
-                SubBlock::LangStrings(LangStrings::from_file(reader)),
+                SubBlock::LangStrings(LangStrings::from_file(reader, keys)),
             Data::KEY_PFIELDS => SubBlock::Data(Data::from_file(reader)),
             Spray::KEY => SubBlock::Spray(Spray::from_file(reader)),
             Crowd::KEY => SubBlock::Crowd(Crowd::from_file(reader)),
-            GameObjs::KEY => SubBlock::GameObjs(GameObjs::from_file(reader)),
+            GameObjs::KEY => SubBlock::GameObjs(GameObjs::from_file(reader, gamemodes)),
             AtlasUV::KEY1 | AtlasUV::KEY2 => SubBlock::AtlasUV(AtlasUV::from_file(reader)),
+            _ if languages.contains(&info.key) && reader.find_val().is_some() =>
+                SubBlock::LangStrings(LangStrings::from_file(reader, keys)),
             _ => match info.key.str() {
                 Some(x) if x.ends_with(".lua") => SubBlock::Lua(Lua::from_file(reader, lua)),
                 Some(x) if x.ends_with(".ssa") => SubBlock::SSA(SSA::from_file(reader)),
@@ -988,16 +1220,30 @@ impl SubBlocks {
         self.header.dump_bytes::<O>().into_iter().chain(block_headers.dump_bytes::<O>().into_iter()).chain(data.into_iter()).collect()
     }
 
-    pub fn to_file(&self, writer: Writer, keys: &StringKeys) {
-        writer.join("index.json").write(&to_vec_pretty(self).unwrap());
+    pub fn to_file(&self, writer: Writer, keys: &StringKeys, gamemodes: &[Crc]) {
+        writer.join("index").write_val(self);
         for (block, info) in zip(&self.blocks, &self.block_headers) {
-            block.to_file(writer.join(info.key.str().unwrap()), keys)
+            block.to_file(writer.join(info.key.str().unwrap()), keys, gamemodes)
         }
     }
 
-    pub fn from_file(reader: Reader, lua: &LuaCompiler) -> Self {
-        let mut val = serde_json::from_slice::<Self>(&reader.join("index.json").read()).unwrap();
-        val.blocks = val.block_headers.iter().map(|info| SubBlock::from_file(reader.join(info.key.str().unwrap()), info, lua)).collect();
+    /// Reads the blocks of the given languages that aren't built in as strings
+    pub fn read_languages<O: ByteOrder + 'static>(&mut self, keys: &StringKeys, languages: &[Crc]) {
+        for (info, block) in zip(&self.block_headers, self.blocks.iter_mut()) {
+            if languages.contains(&info.key) {
+                block.read_language::<O>(&info.key, keys);
+            }
+        }
+    }
+
+    /// Names of the blocks that are read as strings
+    pub fn languages(&self) -> Vec<Crc> {
+        zip(&self.block_headers, &self.blocks).filter(|(_, x)| matches!(x, SubBlock::LangStrings(_))).map(|(x, _)| x.key.clone()).collect()
+    }
+
+    pub fn from_file(reader: Reader, keys: &StringKeys, lua: &LuaCompiler, gamemodes: &[Crc], languages: &[Crc]) -> Self {
+        let mut val = reader.join("index").read_val::<Self>();
+        val.blocks = val.block_headers.iter().map(|info| SubBlock::from_file(reader.join(info.key.str().unwrap()), info, keys, lua, gamemodes, languages)).collect();
         val.header.block_num = val.blocks.len() as u32;
         val
     }
@@ -1056,12 +1302,16 @@ impl StringKeys {
     }
 
     pub fn to_file(&self, writer: Writer) {
-        writer.with_extension("json").write(&to_vec_pretty(&json!(self.vals.iter().map(|x| x.key.to_string()).collect::<Vec<_>>())).unwrap());
+        writer.write_val(&self.vals.iter().map(|x| x.key.to_string()).collect::<Vec<_>>());
     }
 
     pub fn from_file(reader: Reader) -> Self {
-        let vals = serde_json::from_slice::<Value>(&reader.with_extension("json").read()).unwrap();
-        let keys = vals.as_array().unwrap().iter().map(|val| Crc::from_string(val.as_str().unwrap())).collect::<Vec<_>>();
+        let vals = reader.read_val::<Value>();
+        Self::new(vals.as_array().unwrap().iter().map(|val| Crc::from_string(val.as_str().unwrap())).collect())
+    }
+
+    /// Builds the table for a list of keys
+    pub fn new(keys: Vec<Crc>) -> Self {
         let header = StringKeysHeader {
             num_a: keys.len() as u16,
             num_b: keys.len() as u16,
@@ -1112,6 +1362,18 @@ impl LangStrings {
         val
     }
 
+    /// Reads the block of a language that isn't built in, if it is a list of num utf16 strings
+    pub fn detect<O: ByteOrder + 'static>(data: &[u8], num: usize) -> Option<Self> {
+        let size = data.len();
+        if size < 2 || !size.is_multiple_of(2) || data[size - 2..] != [0, 0] {
+            return None;
+        }
+        let vals: Vec<u16> = OrderedDataVec::from_bytes::<O>(data, size / 2);
+        let strings = vals[..vals.len() - 1].split(|x| *x == 0).map(String::from_utf16).collect::<Result<Vec<_>, _>>().ok()?;
+        let val = Self { strings };
+        (val.strings.len() == num && val.dump::<O>() == data).then_some(val)
+    }
+
     pub fn dump<O: ByteOrder + 'static>(&self) -> Vec<u8> {
         let vals = self.strings.iter().flat_map(|x| x.encode_utf16().chain([0u16])).collect::<Vec<_>>();
         vals.dump_bytes::<O>()
@@ -1123,12 +1385,23 @@ impl LangStrings {
 
     pub fn to_file(&self, writer: Writer, keys: &StringKeys) {
         let vals = zip(&keys.vals, &self.strings).map(|(key, string)| (key.key.to_string(), json!(string))).collect::<Map<_,_>>();
-        writer.with_extension("json").write(&to_vec_pretty(&vals).unwrap());
+        writer.write_val(&vals);
     }
 
-    pub fn from_file(reader: Reader) -> Self {
-        let vals = serde_json::from_slice::<Value>(&reader.with_extension("json").read()).unwrap();
-        let strings = vals.as_object().unwrap().iter().map(|(_, s)| s.as_str().unwrap().to_string()).collect::<Vec<_>>();
+    /// Strings are matched to the keys by name, missing ones are left empty
+    pub fn from_file(reader: Reader, keys: &StringKeys) -> Self {
+        let vals = reader.read_val::<Value>();
+        let lookup = vals.as_object().unwrap().iter().map(|(k, s)| (Crc::from_string(k).key(), s.as_str().unwrap())).collect::<HashMap<_, _>>();
+        let strings = keys.vals.iter().map(|x| lookup.get(&x.key.key()).map(|x| x.to_string()).unwrap_or_else(|| {
+            warn!("Missing string {} in {:?}", x.key.to_string(), reader);
+            String::new()
+        })).collect();
+        let known = keys.vals.iter().map(|x| x.key.key()).collect::<HashSet<_>>();
+        for k in vals.as_object().unwrap().keys() {
+            if !known.contains(&Crc::from_string(k).key()) {
+                warn!("String {} in {:?} has no key", k, reader);
+            }
+        }
         Self { strings }
     }
 }
@@ -1177,11 +1450,11 @@ impl SSA {
     }
 
     pub fn to_file(&self, writer: Writer) {
-        writer.with_extension("json").write(&to_vec_pretty(self).unwrap());
+        writer.write_val(self);
     }
 
     pub fn from_file(reader: Reader) -> Self {
-        serde_json::from_slice(&reader.with_extension("json").read()).unwrap()
+        reader.read_val()
     }
 }
 
@@ -1411,9 +1684,14 @@ impl GameObjs {
         // data
     }
 
-    pub fn to_file(&self, writer: Writer) {
-        let val = json!({
-            "gamemodemask": self.gamemodemask,
+    /// Writes the objects with their masks named after the level's `gamemodes`
+    pub fn to_file(&self, writer: Writer, gamemodes: &[Crc]) {
+        writer.write_val(&self.to_json(gamemodes));
+    }
+
+    pub fn to_json(&self, gamemodes: &[Crc]) -> Value {
+        json!({
+            "gamemodemask": mask_to_json(self.gamemodemask, gamemodes),
             "objs": zip(&self.obj_headers,& self.objs).map(|(o, fs)| {
                 let ts = &self.type_fields[*self.type_field_lookup.get(&o.key.key()).unwrap()];
                 let mut order: Vec<_> = (0..ts.len()).collect();
@@ -1421,7 +1699,10 @@ impl GameObjs {
                 json!({
                     "type": o.key.to_string(),
                     "layer": o.layer,
-                    "fields": order.into_iter().map(|i| (ts[i].key.to_string(), fs[i].to_json())).collect::<Map<_,_>>()
+                    "fields": order.into_iter().map(|i| (ts[i].key.to_string(), match &fs[i] {
+                        BaseTypes::Int(val) if ts[i].key.key() == Self::GAMEMODEMASK_FIELD => mask_to_json(*val, gamemodes),
+                        val => val.to_json()
+                    })).collect::<Map<_,_>>()
                 })
             }).collect::<Vec<_>>(),
             "types": zip(&self.types,& self.type_fields).map(|(t, fs)| {
@@ -1438,12 +1719,11 @@ impl GameObjs {
                 })
             }).collect::<Vec<_>>(),
 
-        });
-        writer.with_extension("json").write(&to_vec_pretty(&val).unwrap());
+        })
     }
 
-    pub fn from_file(reader: Reader) -> Self {
-        let val = serde_json::from_slice::<Value>(&reader.with_extension("json").read()).unwrap();
+    pub fn from_file(reader: Reader, gamemodes: &[Crc]) -> Self {
+        let val = reader.read_val::<Value>();
         let ts = val["types"].as_array().unwrap();
         let mut types = Vec::with_capacity(ts.len());
         let mut type_fields = Vec::with_capacity(ts.len());
@@ -1469,7 +1749,12 @@ impl GameObjs {
             let key = Crc::from_string(o["type"].as_str().unwrap());
             let ts = &type_fields[*type_field_lookup.get(&key.key()).unwrap()];
             let o_ = o["fields"].as_object().unwrap();
-            let fields = ts.iter().map(|t| BaseTypes::from_json(&o_[&t.key.to_string()], t.kind.key())).collect::<Vec<_>>();
+            let fields = ts.iter().map(|t| match &o_[&t.key.to_string()] {
+                val @ Value::Array(_) if t.key.key() == Self::GAMEMODEMASK_FIELD => BaseTypes::Int(
+                    mask_from_json(val, gamemodes).unwrap_or_else(|e| panic!("{} in {:?}", e, reader))
+                ),
+                val => BaseTypes::from_json(val, t.kind.key())
+            }).collect::<Vec<_>>();
             let mut off = zip(&fields, ts).map(|(t, f)| f.offset as usize + t.size::<LE>()).fold(0, usize::max);
             off = (off + 15) & 0xFFFFFFF0;
             for (val, t) in zip(&fields, ts) {
@@ -1499,7 +1784,7 @@ impl GameObjs {
             z7: 0
         };
         let size = header.obj_offset as usize + obj_headers.iter().map(|x| x.size as usize).sum::<usize>() + objs.len() * GameObjsObjHeader::size::<LE>() ;
-        let gamemodemask = val["gamemodemask"].as_i64().unwrap() as i32;
+        let gamemodemask = mask_from_json(&val["gamemodemask"], gamemodes).unwrap_or_else(|e| panic!("{} in {:?}", e, reader));
         Self {
             size,
             gamemodemask,
@@ -1513,6 +1798,157 @@ impl GameObjs {
     }
 }
 
+impl GameObjs {
+    pub const GUID_FIELD: u32 = hash_string("GUID".as_bytes(), None);
+    pub const GAMEMODEMASK_FIELD: u32 = hash_string("GameModeMask".as_bytes(), None);
+
+    pub fn fields_of(&self, kind: &Crc) -> &[GameObjsTypeField] {
+        let i = *self.type_field_lookup.get(&kind.key()).unwrap_or_else(|| panic!("Unknown object type {}", kind.to_string()));
+        &self.type_fields[i]
+    }
+
+    fn field_index(&self, obj: usize, name: &Crc) -> Option<usize> {
+        self.fields_of(&self.obj_headers[obj].key).iter().position(|x| x.key == *name)
+    }
+
+    pub fn guid(&self, obj: usize) -> Option<u32> {
+        let i = self.field_index(obj, &Crc::Key(Self::GUID_FIELD))?;
+        match self.objs[obj][i] {
+            BaseTypes::GUID(val) => Some(val),
+            _ => None
+        }
+    }
+
+    pub fn find(&self, guid: u32) -> Option<usize> {
+        (0..self.objs.len()).find(|i| self.guid(*i) == Some(guid))
+    }
+
+    pub fn get_field(&self, guid: u32, name: &Crc) -> Option<&BaseTypes> {
+        let obj = self.find(guid)?;
+        let i = self.field_index(obj, name)?;
+        Some(&self.objs[obj][i])
+    }
+
+    pub fn set_field(&mut self, guid: u32, name: &Crc, val: BaseTypes) {
+        let obj = self.find(guid).unwrap_or_else(|| panic!("No object with guid {}", guid));
+        let i = self.field_index(obj, name).unwrap_or_else(|| panic!("Object {} has no field {}", guid, name.to_string()));
+        let kind = &self.fields_of(&self.obj_headers[obj].key)[i].kind;
+        assert!(kind.key() == val.kind(), "Field {} of object {} is a {}", name.to_string(), guid, kind.to_string());
+        self.objs[obj][i] = val;
+        self.update();
+    }
+
+    /// Adds an object of an existing type with every field defaulted
+    pub fn add_object(&mut self, kind: &Crc, layer: u32, guid: u32) {
+        let fields = self.fields_of(kind).iter().map(|t| 
+            if t.key.key() == Self::GUID_FIELD { BaseTypes::GUID(guid) } else { BaseTypes::new(t.kind.key()) }
+        ).collect();
+        self.insert_object(GameObjsObjHeader { layer, key: kind.clone(), ..Default::default() }, fields);
+    }
+
+    /// Copies an object under a new guid
+    pub fn clone_object(&mut self, guid: u32, new_guid: u32) {
+        let obj = self.find(guid).unwrap_or_else(|| panic!("No object with guid {}", guid));
+        let mut fields = self.objs[obj].clone();
+        if let Some(i) = self.field_index(obj, &Crc::Key(Self::GUID_FIELD)) {
+            fields[i] = BaseTypes::GUID(new_guid);
+        }
+        self.insert_object(self.obj_headers[obj].clone(), fields);
+    }
+
+    pub fn insert_object(&mut self, header: GameObjsObjHeader, fields: Vec<BaseTypes>) {
+        self.insert_objects([(header, fields)]);
+    }
+
+    /// Adds several objects with a single update of the header
+    pub fn insert_objects<I: IntoIterator<Item = (GameObjsObjHeader, Vec<BaseTypes>)>>(&mut self, objs: I) {
+        for (header, fields) in objs {
+            assert!(fields.len() == self.fields_of(&header.key).len(), "Object doesn't match its type {}", header.key.to_string());
+            self.obj_headers.push(header);
+            self.objs.push(fields);
+        }
+        self.update();
+    }
+
+    pub fn remove_object(&mut self, guid: u32) -> Option<(GameObjsObjHeader, Vec<BaseTypes>)> {
+        let obj = self.find(guid)?;
+        let val = (self.obj_headers.remove(obj), self.objs.remove(obj));
+        self.update();
+        Some(val)
+    }
+
+    pub fn add_type(&mut self, header: GameObjsTypeHeader, fields: Vec<GameObjsTypeField>) {
+        assert!(!self.type_field_lookup.contains_key(&header.key.key()), "Type {} already exists", header.key.to_string());
+        self.type_field_lookup.insert(header.key.key(), self.types.len());
+        self.types.push(header);
+        self.type_fields.push(fields);
+        self.update();
+    }
+
+    /// Copies the definition of a type from another GameObjs. If the type already exists the definitions are merged,
+    /// fields only present in src are added (with default values for the existing objects) as long as they don't overlap
+    pub fn import_type(&mut self, src: &GameObjs, kind: &Crc) -> Result<(), String> {
+        let Some(i) = src.type_field_lookup.get(&kind.key()) else {
+            return Err(format!("Type {} doesn't exist in the source", kind.to_string()));
+        };
+        let Some(j) = self.type_field_lookup.get(&kind.key()).cloned() else {
+            self.add_type(src.types[*i].clone(), src.type_fields[*i].clone());
+            return Ok(());
+        };
+        let range = |t: &GameObjsTypeField| t.offset as usize..t.offset as usize + BaseTypes::new(t.kind.key()).size::<LE>();
+        let mut new_fields = vec![];
+        for field in &src.type_fields[*i] {
+            match self.type_fields[j].iter().find(|x| x.key == field.key) {
+                Some(x) if x.kind != field.kind || x.offset != field.offset => return Err(format!(
+                    "Field {} of type {} is a {} at {} but a {} at {} in the source",
+                    field.key.to_string(), kind.to_string(), x.kind.to_string(), x.offset, field.kind.to_string(), field.offset
+                )),
+                Some(_) => (),
+                None => {
+                    let r = range(field);
+                    if let Some(x) = self.type_fields[j].iter().chain(&new_fields).find(|x| { let o = range(x); o.start < r.end && r.start < o.end }) {
+                        return Err(format!("Field {} of type {} overlaps {}", field.key.to_string(), kind.to_string(), x.key.to_string()));
+                    }
+                    new_fields.push(field.clone());
+                }
+            }
+        }
+        if !new_fields.is_empty() {
+            for (header, fields) in zip(&self.obj_headers, self.objs.iter_mut()) {
+                if header.key == *kind {
+                    fields.extend(new_fields.iter().map(|x| BaseTypes::new(x.kind.key())));
+                }
+            }
+            self.type_fields[j].extend(new_fields);
+            self.types[j].size = self.type_fields[j].len() as u32;
+            self.update();
+        }
+        Ok(())
+    }
+
+    /// Reorders the fields of an object from another GameObjs to match the layout of its type here, missing fields get default values
+    pub fn convert_fields(&self, src: &GameObjs, kind: &Crc, fields: &[BaseTypes]) -> Vec<BaseTypes> {
+        let src_fields = src.fields_of(kind);
+        self.fields_of(kind).iter().map(|t| match src_fields.iter().position(|x| x.key == t.key) {
+            Some(i) => fields[i].clone(),
+            None => BaseTypes::new(t.kind.key())
+        }).collect()
+    }
+
+    /// Recomputes the header counts and sizes after the objects have been changed
+    pub fn update(&mut self) {
+        let data = self.dump::<LE>();
+        let header: GameObjsHeader = OrderedData::from_bytes::<LE>(&data);
+        let mut offset = header.obj_offset as usize;
+        for obj in self.obj_headers.iter_mut() {
+            obj.size = GameObjsObjHeader::from_bytes::<LE>(&data[offset..]).size;
+            offset += GameObjsObjHeader::size::<LE>() + obj.size as usize;
+        }
+        self.header = header;
+        self.size = data.len();
+    }
+}
+
 #[derive(Debug, Default, Clone, OrderedData, Serialize, Deserialize)]
 struct SprayObj1 {
     pub key: Crc,
@@ -1587,11 +2023,11 @@ impl Spray {
     }
 
     pub fn to_file(&self, writer: Writer) {
-        writer.with_extension("json").write(&to_vec_pretty(self).unwrap());
+        writer.write_val(self);
     }
 
     pub fn from_file(reader: Reader) -> Self {
-        serde_json::from_slice(&reader.with_extension("json").read()).unwrap()
+        reader.read_val()
     }
 }
 
@@ -1670,11 +2106,11 @@ impl Crowd {
     }
 
     pub fn to_file(&self, writer: Writer) {
-        writer.with_extension("json").write(&to_vec_pretty(self).unwrap());
+        writer.write_val(self);
     }
 
     pub fn from_file(reader: Reader) -> Self {
-        serde_json::from_slice(&reader.with_extension("json").read()).unwrap()
+        reader.read_val()
     }
 }
 
@@ -1708,11 +2144,11 @@ impl AtlasUV {
     }
 
     pub fn to_file(&self, writer: Writer) {
-        writer.with_extension("json").write(&to_vec_pretty(self).unwrap());
+        writer.write_val(self);
     }
 
     pub fn from_file(reader: Reader) -> Self {
-        serde_json::from_slice(&reader.with_extension("json").read()).unwrap()
+        reader.read_val()
     }
 }
 
@@ -1746,4 +2182,71 @@ impl Data {
     pub fn from_file(reader: Reader) -> Self {
         Self { data: reader.read() }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gamemode_masks() {
+        assert_eq!(mask_to_json(5u32, &[]), json!(5));
+        assert_eq!(mask_from_json::<u32>(&json!(5), &[]), Ok(5));
+        assert_eq!(mask_from_json::<i32>(&json!(-1), &[]), Ok(-1));
+
+        let names = [Crc::from_string("Conquest"), Crc::from_string("Assault")];
+        assert_eq!(mask_to_json(0b1011u32, &names), json!(["Conquest", "Assault", 3]));
+        for mask in [0u32, 1, 0b1011, 0x8000_0001, u32::MAX] {
+            assert_eq!(mask_from_json::<u32>(&mask_to_json(mask, &names), &names), Ok(mask));
+        }
+        assert_eq!(mask_from_json::<i32>(&mask_to_json(-1i32, &names), &names), Ok(-1));
+        // names match by crc and numbers are still read
+        assert_eq!(mask_from_json::<u32>(&json!(["assault", 4]), &names), Ok(0b10010));
+        assert_eq!(mask_from_json::<u32>(&json!(3), &names), Ok(3));
+
+        assert!(mask_from_json::<u32>(&json!(["Unknown"]), &names).is_err());
+        assert!(mask_from_json::<u32>(&json!(["Assault"]), &[]).is_err());
+        assert!(mask_from_json::<u32>(&json!([32]), &names).is_err());
+        assert!(mask_from_json::<u32>(&json!([-1]), &names).is_err());
+        assert!(mask_from_json::<u32>(&json!("Conquest"), &names).is_err());
+    }
+
+    #[test]
+    fn gamemode_mask_fields() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Info {
+            #[serde(with = "gamemode_mask")]
+            gamemodemask: i32,
+        }
+        let names = [Crc::from_string("Conquest"), Crc::from_string("Assault")];
+        let val = with_gamemodes(&names, || serde_json::to_value(Info { gamemodemask: 0b110 }).unwrap());
+        assert_eq!(val, json!({"gamemodemask": ["Assault", 2]}));
+        // the names only apply inside the call, also on other threads
+        assert_eq!(serde_json::to_value(Info { gamemodemask: 0b110 }).unwrap(), json!({"gamemodemask": 6}));
+        assert!(serde_json::from_value::<Info>(val.clone()).is_err());
+        let other = std::thread::spawn(move || with_gamemodes(&names, || serde_json::from_value::<Info>(val).unwrap()));
+        assert_eq!(other.join().unwrap(), Info { gamemodemask: 0b110 });
+    }
+
+    #[test]
+    fn added_languages() {
+        let keys = StringKeys::new(vec![Crc::from_string("KEY_A"), Crc::from_string("KEY_B")]);
+        let data = LangStrings { strings: vec!["a".to_string(), String::new()] }.dump::<LE>();
+        let mut blocks = SubBlocks::default();
+        for name in ["Dutch", "Other", "Short"] {
+            blocks.block_headers.push(SubBlocksBlockHeader { key: Crc::from_string(name), ..Default::default() });
+        }
+        blocks.blocks = vec![
+            SubBlock::Data(Data { data: data.clone() }),
+            SubBlock::Data(Data { data: data.clone() }),
+            SubBlock::Data(Data { data: data[..4].to_vec() }),
+        ];
+        // only named languages with a string for every key are read
+        blocks.read_languages::<LE>(&keys, &[Crc::from_string("Dutch"), Crc::from_string("Short")]);
+        assert!(matches!(&blocks.blocks[0], SubBlock::LangStrings(x) if x.strings == ["a", ""]));
+        assert!(matches!(blocks.blocks[1], SubBlock::Data(_)));
+        assert!(matches!(blocks.blocks[2], SubBlock::Data(_)));
+        // which are the ones a dump lists to read back as strings
+        assert_eq!(blocks.languages(), vec![Crc::from_string("Dutch")]);
+    }
+}